@@ -0,0 +1,375 @@
+// The window's side of ProgressReporter: the log listview with its filter
+// and trimming, and the queue that carries reports from the install thread.
+
+use native_windows_gui as nwg;
+use app_installer::reporter::ProgressReporter;
+use once_cell::sync::Lazy;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex};
+
+pub static MAX_LOG_ROWS: Lazy<Mutex<usize>> = Lazy::new(|| 
+        Mutex::new(10_000));
+/// Every row shown so far, whether or not the filter currently hides it.
+static LOG: Lazy<Mutex<Log>> = Lazy::new(|| Mutex::new(Log::default()));
+static LOG_FILTER: Lazy<Mutex<LogFilter>> = Lazy::new(|| 
+        Mutex::new(LogFilter::default()));
+static LOG_SORT: Lazy<Mutex<LogSort>> = Lazy::new(|| 
+        Mutex::new(LogSort::default()));
+/// Whether the progress bar is showing its marquee.
+static BAR_INDETERMINATE: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+/// Milliseconds between steps of the marquee animation.
+const MARQUEE_INTERVAL: u32 = 30;
+
+struct LogRow {
+    message_type: String,
+    time_str: String,
+    message: String,
+    /// Arrival order, which breaks ties when sorting.
+    seq: u64,
+}
+
+#[derive(Default)]
+struct Log {
+    rows: VecDeque<LogRow>,
+    /// Rows dropped from the front to stay under MAX_LOG_ROWS.
+    trimmed: usize,
+    /// Time of the most recent trim, shown on the notice row.
+    trimmed_at: String,
+    next_seq: u64,
+}
+
+/// Which rows the listview shows. An empty text or type matches everything.
+#[derive(Default)]
+struct LogFilter {
+    text: String,
+    message_type: Option<String>,
+}
+
+impl LogFilter {
+    fn matches(&self, row: &LogRow) -> bool {
+        if self.message_type.as_ref()
+                .is_some_and(|message_type| &row.message_type != message_type) {
+            return false;
+        }
+        self.text.is_empty() || 
+                row.message.to_lowercase().contains(&self.text)
+    }
+}
+
+/// The listview's order: a column (0 type, 1 time, 2 message) and a 
+/// direction. Time ascending is the order the rows arrive in, so new rows 
+/// are simply appended.
+#[derive(Clone, Copy)]
+struct LogSort {
+    column: usize,
+    ascending: bool,
+}
+
+impl Default for LogSort {
+    fn default() -> Self {
+        LogSort { column: 1, ascending: true }
+    }
+}
+
+impl LogSort {
+    fn is_arrival_order(&self) -> bool {
+        self.column == 1 && self.ascending
+    }
+
+    /// Types sort by severity and messages ignoring case.
+    fn compare(&self, a: &LogRow, b: &LogRow) -> Ordering {
+        let order = match self.column {
+            0 => type_rank(&a.message_type).cmp(&type_rank(&b.message_type)),
+            1 => a.time_str.cmp(&b.time_str),
+            _ => a.message.to_lowercase().cmp(&b.message.to_lowercase()),
+        }.then(a.seq.cmp(&b.seq));
+        if self.ascending { order } else { order.reverse() }
+    }
+}
+
+fn type_rank(message_type: &str) -> Option<usize> {
+    ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"].iter()
+        .position(|name| *name == message_type)
+}
+
+/// The listview index `row` has, or would have, among the rows the filter
+/// shows, counting the trimmed notice at the top.
+fn visible_index(log: &Log, filter: &LogFilter, sort: &LogSort, 
+        row: &LogRow) -> usize {
+    let before = log.rows.iter()
+        .filter(|other| filter.matches(other) && 
+                sort.compare(other, row) == Ordering::Less)
+        .count();
+    before + usize::from(log.trimmed > 0)
+}
+
+pub struct ListViewReporter<'a> {
+    listview: &'a nwg::ListView,
+    bar: &'a nwg::ProgressBar,
+    status: &'a nwg::Label,
+}
+
+impl<'a> ListViewReporter<'a> {
+    pub fn new(listview: &'a nwg::ListView, bar: &'a nwg::ProgressBar, 
+            status: &'a nwg::Label) -> ListViewReporter<'a> {
+        ListViewReporter { listview, bar, status }
+    }
+
+    /// Switches the bar to a marquee, for steps with no measurable progress,
+    /// or back to showing a percentage.
+    pub fn set_indeterminate(&self, indeterminate: bool) {
+        let mut current = BAR_INDETERMINATE.lock().unwrap();
+        if *current == indeterminate {
+            return;
+        }
+        *current = indeterminate;
+        if indeterminate {
+            self.bar.add_flags(nwg::ProgressBarFlags::MARQUEE);
+            self.bar.set_marquee(true, MARQUEE_INTERVAL);
+        } else {
+            self.bar.set_marquee(false, 0);
+            self.bar.remove_flags(nwg::ProgressBarFlags::MARQUEE);
+        }
+    }
+}
+
+impl ProgressReporter for ListViewReporter<'_> {
+    fn message(&self, message_type: &str, time_str: &str, message: &str) {
+        let mut log = LOG.lock().unwrap();
+        let filter = LOG_FILTER.lock().unwrap();
+        let sort = *LOG_SORT.lock().unwrap();
+        let row = LogRow {
+            message_type: message_type.to_string(),
+            time_str: time_str.to_string(),
+            message: message.to_string(),
+            seq: log.next_seq,
+        };
+        log.next_seq += 1;
+        trim_log(self.listview, &mut log, &filter, &sort, time_str);
+        if filter.matches(&row) {
+            let index = if sort.is_arrival_order() {
+                None
+            } else {
+                Some(visible_index(&log, &filter, &sort, &row) as i32)
+            };
+            insert_row(self.listview, index, &row);
+        }
+        log.rows.push_back(row);
+    }
+
+    /// Leaves a finished phase at 100 until the next one reports its 0.
+    fn progress(&self, progress: u32, status: &str) {
+        self.set_indeterminate(false);
+        self.bar.set_pos(progress.min(100));
+        self.status.set_text(status);
+    }
+
+    fn busy(&self, status: &str) {
+        self.set_indeterminate(true);
+        self.status.set_text(status);
+    }
+
+    fn confirm(&self, title: &str, prompt: &str) -> bool {
+        let choice = nwg::message(&nwg::MessageParams {
+            title,
+            content: prompt,
+            buttons: nwg::MessageButtons::YesNo,
+            icons: nwg::MessageIcons::Warning,
+        });
+        choice == nwg::MessageChoice::Yes
+    }
+}
+
+fn insert_row(listview: &nwg::ListView, index: Option<i32>, row: &LogRow) {
+    listview.insert_items_row(index, &[row.message_type.as_str(), 
+            row.time_str.as_str(), row.message.as_str()]);
+}
+
+fn trimmed_notice(log: &Log) -> LogRow {
+    LogRow {
+        message_type: "INFO".to_string(),
+        time_str: log.trimmed_at.clone(),
+        message: format!("{} earlier messages trimmed", log.trimmed),
+        seq: 0,
+    }
+}
+
+/// Keeps the log at most MAX_LOG_ROWS rows. Once the cap is hit each new 
+/// message drops the oldest one, and the listview's first row becomes a 
+/// notice counting the trimmed messages.
+fn trim_log(listview: &nwg::ListView, log: &mut Log, filter: &LogFilter, 
+        sort: &LogSort, time_str: &str) {
+    let max_rows = (*MAX_LOG_ROWS.lock().unwrap()).max(1);
+    if log.rows.len() < max_rows {
+        return;
+    }
+    let had_notice = log.trimmed > 0;
+    let oldest_index = log.rows.front()
+        .filter(|oldest| filter.matches(oldest))
+        .map(|oldest| if sort.is_arrival_order() {
+            usize::from(had_notice)
+        } else {
+            visible_index(log, filter, sort, oldest)
+        });
+    log.rows.pop_front();
+    if let Some(index) = oldest_index {
+        listview.remove_item(index);
+    }
+    log.trimmed += 1;
+    log.trimmed_at = time_str.to_string();
+    if had_notice {
+        listview.remove_item(0);
+    }
+    insert_row(listview, Some(0), &trimmed_notice(log));
+}
+
+/// The log as tab-separated lines (type, time, message) in the order the 
+/// messages arrived, after the trimmed notice if there is one. With 
+/// `filtered` only the rows the filter shows are included. The rows come 
+/// from the log itself, so long messages aren't cut short as the 
+/// listview's cells would be.
+pub fn log_text(filtered: bool) -> String {
+    let log = LOG.lock().unwrap();
+    let filter = LOG_FILTER.lock().unwrap();
+    let notice = (log.trimmed > 0).then(|| trimmed_notice(&log));
+    let rows = log.rows.iter()
+        .filter(|row| !filtered || filter.matches(row));
+    let mut text = String::new();
+    for row in notice.iter().chain(rows) {
+        text.push_str(&format!("{}\t{}\t{}\r\n", row.message_type, 
+                row.time_str, row.message));
+    }
+    text
+}
+
+/// Shows only the rows whose message contains `text` (ignoring case) and, 
+/// when given, whose type is `message_type`. Clearing both shows every row.
+pub fn set_log_filter(listview: &nwg::ListView, text: &str, 
+        message_type: Option<&str>) {
+    let mut filter = LOG_FILTER.lock().unwrap();
+    filter.text = text.trim().to_lowercase();
+    filter.message_type = message_type.map(str::to_string);
+    show_log(listview, &filter);
+}
+
+/// Sorts the log by `column`, toggling the direction when it already is 
+/// the sort column, and shows the direction with the header's arrow.
+pub fn sort_log(listview: &nwg::ListView, column: usize) {
+    {
+        let mut sort = LOG_SORT.lock().unwrap();
+        *sort = LogSort {
+            column,
+            ascending: sort.column != column || !sort.ascending,
+        };
+    }
+    show_sort_arrow(listview);
+    show_log(listview, &LOG_FILTER.lock().unwrap());
+}
+
+/// Puts the arrow on the sort column's header, up for ascending.
+pub fn show_sort_arrow(listview: &nwg::ListView) {
+    let sort = *LOG_SORT.lock().unwrap();
+    for column in 0..3 {
+        let arrow = match (column == sort.column, sort.ascending) {
+            (false, _) => None,
+            (true, true) => Some(nwg::ListViewColumnSortArrow::Up),
+            (true, false) => Some(nwg::ListViewColumnSortArrow::Down),
+        };
+        listview.set_column_sort_arrow(column, arrow);
+    }
+}
+
+/// Refills the listview with the rows `filter` shows, in LOG_SORT order.
+fn show_log(listview: &nwg::ListView, filter: &LogFilter) {
+    let log = LOG.lock().unwrap();
+    let sort = *LOG_SORT.lock().unwrap();
+    let mut rows: Vec<&LogRow> = log.rows.iter()
+        .filter(|row| filter.matches(row))
+        .collect();
+    if !sort.is_arrival_order() {
+        rows.sort_by(|a, b| sort.compare(a, b));
+    }
+
+    listview.set_redraw(false);
+    listview.clear();
+    if log.trimmed > 0 {
+        insert_row(listview, None, &trimmed_notice(&log));
+    }
+    for row in rows {
+        insert_row(listview, None, row);
+    }
+    listview.set_redraw(true);
+}
+
+/// A report made on the install thread, waiting to be shown by the UI thread.
+pub enum ReportEvent {
+    Message { message_type: String, time_str: String, message: String },
+    Progress { progress: u32, status: String },
+    Busy { status: String },
+    Confirm { title: String, prompt: String, reply: mpsc::Sender<bool> },
+    /// The install thread is done; true if the install succeeded.
+    Finished(bool),
+}
+
+pub type ReportQueue = Arc<Mutex<VecDeque<ReportEvent>>>;
+
+/// Used on the install thread, which can't touch the window's controls. 
+/// Each report is queued and the UI thread is woken through a Notice to 
+/// apply it; confirm blocks until the UI thread has the user's answer.
+pub struct ThreadReporter {
+    queue: ReportQueue,
+    notice: nwg::NoticeSender,
+}
+
+impl ThreadReporter {
+    pub fn new(queue: ReportQueue, notice: nwg::NoticeSender) -> 
+            ThreadReporter {
+        ThreadReporter { queue, notice }
+    }
+
+    pub fn send(&self, event: ReportEvent) {
+        self.queue.lock().unwrap().push_back(event);
+        self.notice.notice();
+    }
+}
+
+impl ProgressReporter for ThreadReporter {
+    fn message(&self, message_type: &str, time_str: &str, message: &str) {
+        self.send(ReportEvent::Message {
+            message_type: message_type.to_string(),
+            time_str: time_str.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    fn progress(&self, progress: u32, status: &str) {
+        // A copy reports every chunk; only the latest value needs showing.
+        let mut queue = self.queue.lock().unwrap();
+        if let Some(ReportEvent::Progress { progress: last_progress, 
+                status: last_status }) = queue.back_mut() {
+            *last_progress = progress;
+            *last_status = status.to_string();
+            return;
+        }
+        queue.push_back(ReportEvent::Progress { progress, 
+                status: status.to_string() });
+        drop(queue);
+        self.notice.notice();
+    }
+
+    fn busy(&self, status: &str) {
+        self.send(ReportEvent::Busy { status: status.to_string() });
+    }
+
+    fn confirm(&self, title: &str, prompt: &str) -> bool {
+        let (reply, answer) = mpsc::channel();
+        self.send(ReportEvent::Confirm {
+            title: title.to_string(),
+            prompt: prompt.to_string(),
+            reply,
+        });
+        answer.recv().unwrap_or(false)
+    }
+}
@@ -0,0 +1,105 @@
+// Downloads over HTTP/HTTPS with WinINet, which ships with Windows, rather 
+// than pulling an HTTP client and TLS stack into the executable.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::um::wininet::{HINTERNET, HttpQueryInfoW, InternetCloseHandle, 
+        InternetOpenUrlW, InternetOpenW, InternetReadFile, 
+        HTTP_QUERY_CONTENT_LENGTH, HTTP_QUERY_FLAG_NUMBER, 
+        HTTP_QUERY_STATUS_CODE, INTERNET_FLAG_NO_CACHE_WRITE, 
+        INTERNET_FLAG_RELOAD, INTERNET_OPEN_TYPE_PRECONFIG};
+
+struct InternetHandle(HINTERNET);
+
+impl Drop for InternetHandle {
+    fn drop(&mut self) {
+        unsafe { InternetCloseHandle(self.0); }
+    }
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
+
+pub fn is_url(source: &str) -> bool {
+    let source = source.to_ascii_lowercase();
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+fn query_number(request: &InternetHandle, info_level: DWORD) -> Option<u32> {
+    let mut value: DWORD = 0;
+    let mut len = std::mem::size_of::<DWORD>() as DWORD;
+    let result = unsafe {
+        HttpQueryInfoW(request.0, info_level | HTTP_QUERY_FLAG_NUMBER, 
+                &mut value as *mut DWORD as LPVOID, &mut len, 
+                std::ptr::null_mut())
+    };
+    if result == 0 { None } else { Some(value) }
+}
+
+/// Streams `url` into the file at `to`, calling `progress` with a percentage
+/// when the server sends a Content-Length. Stops with an Interrupted error 
+/// once `cancelled` returns true.
+pub fn download_with_progress<F: FnMut(u32), C: Fn() -> bool>(url: &str, 
+        to: &Path, mut progress: F, cancelled: C) -> io::Result<()> {
+    let agent = to_wide("AppInstaller");
+    let session = unsafe {
+        InternetOpenW(agent.as_ptr(), INTERNET_OPEN_TYPE_PRECONFIG, 
+                std::ptr::null(), std::ptr::null(), 0)
+    };
+    if session.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let session = InternetHandle(session);
+
+    let wide_url = to_wide(url);
+    let request = unsafe {
+        InternetOpenUrlW(session.0, wide_url.as_ptr(), std::ptr::null(), 0,
+                INTERNET_FLAG_RELOAD | INTERNET_FLAG_NO_CACHE_WRITE, 0)
+    };
+    if request.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let request = InternetHandle(request);
+
+    // Only HTTP responses carry a status code.
+    if let Some(status) = query_number(&request, HTTP_QUERY_STATUS_CODE) && 
+            status != 200 {
+        return Err(io::Error::other(
+                format!("Server returned HTTP status {}", status)));
+    }
+    let content_length = query_number(&request, HTTP_QUERY_CONTENT_LENGTH)
+        .map(|len| len as u64)
+        .filter(|len| *len > 0);
+
+    let mut to_file = File::create(to)?;
+    let mut buffer = [0u8; 8192];
+    let mut bytes_copied = 0;
+    loop {
+        if cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, 
+                    "Download cancelled"));
+        }
+        let mut bytes_read: DWORD = 0;
+        let result = unsafe {
+            InternetReadFile(request.0, buffer.as_mut_ptr() as LPVOID, 
+                    buffer.len() as DWORD, &mut bytes_read)
+        };
+        if result == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if bytes_read == 0 {
+            break;
+        }
+        to_file.write_all(&buffer[..bytes_read as usize])?;
+        bytes_copied += bytes_read as u64;
+        if let Some(total) = content_length {
+            progress((bytes_copied * 100 / total) as u32);
+        }
+    }
+    Ok(())
+}
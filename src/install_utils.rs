@@ -1,464 +1,3516 @@
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::env;
-use std::time::SystemTime;
-use std::fs::{self, File};
+use std::fmt;
+use std::thread;
+use std::sync::mpsc;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Read, Write, BufReader};
-use std::os::windows::ffi::OsStringExt;
-use std::path::{Path, PathBuf};
-use sysinfo::{System, SystemExt};
-use winapi::um::knownfolders::FOLDERID_LocalAppData;
-use winapi::um::shlobj::CSIDL_STARTMENU;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::process::Command;
+use sysinfo::{Pid, ProcessExt, ProcessRefreshKind, RefreshKind, System, 
+        SystemExt};
+use winapi::um::knownfolders::{FOLDERID_Desktop, FOLDERID_LocalAppData, 
+        FOLDERID_ProgramFiles, FOLDERID_PublicDesktop};
+use winapi::um::shlobj::{CSIDL_STARTMENU, CSIDL_COMMON_STARTMENU};
 use winapi::um::shlobj::SHGetSpecialFolderPathW;
 use winapi::um::shlobj::{SHGetKnownFolderPath};
+use winapi::um::shtypes::REFKNOWNFOLDERID;
 use winapi::um::winnt::PWSTR;
 use winapi::shared::winerror::S_OK;
-use mslnk::ShellLink;
+use winapi::um::combaseapi::CoTaskMemFree;
+use winapi::shared::minwindef::LPVOID;
+use winapi::um::fileapi::GetDiskFreeSpaceExW;
+use winapi::um::processenv::ExpandEnvironmentStringsW;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::winnt::ULARGE_INTEGER;
+use winapi::um::synchapi::{CreateMutexW, ReleaseMutex, WaitForSingleObject};
+use winapi::um::winbase::{WAIT_ABANDONED, WAIT_OBJECT_0};
+use winapi::um::winnt::{HANDLE, TOKEN_ELEVATION, TOKEN_QUERY, 
+        TokenElevation};
+use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
+use winapi::um::securitybaseapi::GetTokenInformation;
+use winapi::ctypes::c_int;
+use winreg::RegKey;
+use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+use mslnk::{LinkFlags, ShellLink};
 use parselnk::Lnk;
 use chrono::Local;
-use native_windows_gui as nwg;
-use crate::zip_utils;
-use crate::{EXE_PATH_TO_RUN, DEBUG};
+use crate::{http_utils, version_utils, zip_utils};
+#[cfg(feature = "sevenz")]
+use crate::sevenz_utils;
+use crate::zip_utils::{ArchiveFormat, LinkKind, ZipError};
+use crate::reporter::{LogFormat, LogLevel, ProgressReporter, LOG_FORMAT, 
+        LOG_LEVEL};
+use crate::{EXE_PATH_TO_RUN, SKIP_SELF_UPDATE, ALLOW_DOWNGRADE, 
+        CONFIRM_REMOVAL};
+use crate::{DESKTOP_SHORTCUT, DRY_RUN};
 use once_cell::sync::Lazy;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 pub static REMOTE_DIR: Lazy<Mutex<PathBuf>> = Lazy::new(|| 
         Mutex::new(PathBuf::from(r"C:\dev\apps")));
+pub static NEWEST_BY: Lazy<Mutex<NewestBy>> = Lazy::new(|| 
+        Mutex::new(NewestBy::Version));
+/// With --channel, each app's zips are looked for in this subfolder of its
+/// source folder, e.g. `<source>\<app>\beta`. The installer has no
+/// channels.
+pub static CHANNEL: Lazy<Mutex<Option<String>>> = Lazy::new(|| 
+        Mutex::new(None));
+/// With --version, apps are installed from the zip with exactly this version
+/// in its name instead of the newest. The installer itself is not pinned.
+pub static PINNED_VERSION: Lazy<Mutex<Option<version_utils::Version>>> = 
+        Lazy::new(|| Mutex::new(None));
+/// With --zip, or Browse... in the window, apps are installed from this file
+/// instead of a zip found in the source. It is read where it is and never 
+/// deleted.
+pub static LOCAL_ZIP: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| 
+        Mutex::new(None));
+/// --stdin: the app's zip is piped in rather than found in the source. It 
+/// is saved to the Utils folder, installed and deleted again.
+pub static ZIP_FROM_STDIN: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+/// Set from the UI thread to stop the install running on the worker thread.
+/// Checked between files and between chunks of a copy or download.
+pub static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// With --timeout, an install still running after this long is cancelled 
+/// and reported as timed out.
+pub static INSTALL_TIMEOUT: Lazy<Mutex<Option<Duration>>> = Lazy::new(|| 
+        Mutex::new(None));
+/// Set by the watchdog when it cancels the install, so the cancellation is
+/// reported as a timeout.
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+/// Set once a self-update has started the new installer; the caller should
+/// exit without running the app.
+pub static INSTALLER_RESTARTED: AtomicBool = AtomicBool::new(false);
+/// The apps installed in this run whose manifest says Windows must restart
+/// before they work. Cleared by start_run.
+pub static REBOOT_REQUIRED: Lazy<Mutex<Vec<String>>> = Lazy::new(|| 
+        Mutex::new(Vec::new()));
+/// The installer's working folder under LOCALAPPDATA, holding the installer
+/// itself, its log, the zips being installed and, by default, the apps.
+pub const DEFAULT_STAGING_DIR: &str = "Utils";
+/// --staging-dir: use this folder instead of LOCALAPPDATA\Utils. A relative
+/// path is taken from LOCALAPPDATA.
+pub static STAGING_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| 
+        Mutex::new(None));
+/// Folder apps are installed into, one subfolder each, instead of 
+/// LOCALAPPDATA\Utils.
+pub static INSTALL_ROOT: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| 
+        Mutex::new(None));
+/// --all-users: install into Program Files with shortcuts in the common 
+/// Start Menu (and the Public Desktop) and the Apps & features entry under
+/// HKEY_LOCAL_MACHINE. Needs an elevated installer. The installer itself 
+/// stays in LOCALAPPDATA\Utils.
+pub static ALL_USERS: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
+/// Check each zip against the SHA-256 in its `<zip>.sha256` sidecar.
+pub static VERIFY_CHECKSUM: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+/// How many times a failed copy of the zip is retried.
+pub static COPY_RETRIES: Lazy<Mutex<u32>> = Lazy::new(|| Mutex::new(2));
+/// Size of each read and write when copying the zip. Small chunks make a
+/// copy from a network share crawl.
+pub static COPY_BUFFER_SIZE: Lazy<Mutex<usize>> = Lazy::new(|| 
+        Mutex::new(1024 * 1024));
+/// Command line stored in the shortcut, passed to the app as-is.
+pub static SHORTCUT_ARGS: Lazy<Mutex<Option<String>>> = Lazy::new(|| 
+        Mutex::new(None));
+/// Extract zips that have several entries with the same name, keeping the 
+/// first of each, instead of refusing them.
+pub static ALLOW_DUPLICATE_ENTRIES: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+/// Add an entry for each installed app to Apps & features (Add/Remove 
+/// Programs).
+pub static REGISTER_UNINSTALL: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+/// Working directory for the shortcut. Relative paths are resolved against
+/// the install folder, so "." starts the app in its own folder.
+pub static SHORTCUT_WORKDIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| 
+        Mutex::new(None));
 
-pub fn get_local_appdata(listview: &nwg::ListView) -> Option<PathBuf> {
-    let mut path_ptr: PWSTR = std::ptr::null_mut();
-    let result = unsafe {
-        SHGetKnownFolderPath(
-            &FOLDERID_LocalAppData,
-            0,
-            std::ptr::null_mut(),
-            &mut path_ptr
-        )
+/// How the "latest" zip in a source directory is chosen.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NewestBy {
+    /// Highest semantic version in the file name. Falls back to Mtime when 
+    /// no file name contains a version.
+    Version,
+    /// Most recently modified file.
+    Mtime,
+}
+
+/// The staging folder, LOCALAPPDATA\Utils unless --staging-dir moves it.
+/// Nothing is created.
+pub fn staging_dir() -> Option<PathBuf> {
+    let dir = STAGING_DIR.lock().unwrap().clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_STAGING_DIR));
+    // Joining an absolute path replaces the root.
+    Some(get_local_appdata_root()?.join(dir))
+}
+
+/// The staging folder, created if need be. start_run has already checked
+/// that LOCALAPPDATA exists.
+pub fn get_local_appdata(reporter: &dyn ProgressReporter) -> Option<PathBuf> {
+    let path = staging_dir()?;
+    if !path.exists() && let Err(e) = fs::create_dir_all(&path) {
+        add_message(reporter, LogLevel::Error,
+            &format!("Failed to create directory {:?}: {}", path, e));
+        return None;
+    }
+    Some(path)
+}
+
+/// The folder apps are installed into: --install-root when given, otherwise
+/// Program Files with --all-users and LOCALAPPDATA\Utils without.
+fn install_root(reporter: &dyn ProgressReporter) -> Option<PathBuf> {
+    match INSTALL_ROOT.lock().unwrap().clone() {
+        Some(root) => Some(root),
+        None if all_users() => all_users_root(),
+        None => get_local_appdata(reporter),
+    }
+}
+
+fn all_users() -> bool {
+    *ALL_USERS.lock().unwrap()
+}
+
+/// Where --all-users installs apps when no --install-root is given.
+fn all_users_root() -> Option<PathBuf> {
+    get_known_folder(&FOLDERID_ProgramFiles)
+}
+
+/// Whether `path` belongs to an all-users install: it is below Program 
+/// Files, the common Start Menu or the Public Desktop.
+fn is_all_users_path(path: &Path) -> bool {
+    let path = comparable_path(path);
+    all_users_root().into_iter()
+        .chain(get_common_start_menu_path())
+        .chain(get_public_desktop_path())
+        .any(|root| path.starts_with(comparable_path(&root)))
+}
+
+/// Whether the installer runs with administrator rights, i.e. elevated past
+/// UAC, which an all-users install needs.
+pub fn is_elevated() -> bool {
+    let mut token: HANDLE = std::ptr::null_mut();
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, 
+            &mut token) } == 0 {
+        return false;
+    }
+    let mut elevation: TOKEN_ELEVATION = unsafe { std::mem::zeroed() };
+    let mut size = 0;
+    let ok = unsafe {
+        GetTokenInformation(token, TokenElevation, 
+                &mut elevation as *mut TOKEN_ELEVATION as LPVOID, 
+                std::mem::size_of::<TOKEN_ELEVATION>() as u32, &mut size)
+    };
+    unsafe { CloseHandle(token) };
+    ok != 0 && elevation.TokenIsElevated != 0
+}
+
+/// Refuses --all-users without administrator rights, before anything has 
+/// been changed.
+fn check_elevation(reporter: &dyn ProgressReporter) 
+        -> Result<(), InstallError> {
+    if all_users() && !is_elevated() {
+        add_message(reporter, LogLevel::Error, 
+                "--all-users needs administrator rights to write to Program \
+                Files and the common Start Menu. Run the installer as \
+                administrator.");
+        return Err(InstallError::NotElevated);
+    }
+    Ok(())
+}
+
+/// Where `app_name` is installed. The installer itself always lives under 
+/// LOCALAPPDATA\Utils, where the self-update looks for it.
+fn get_app_dir(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Option<PathBuf> {
+    let root = if app_name == "AppInstaller" {
+        get_local_appdata(reporter)
+    } else {
+        install_root(reporter)
+    };
+    root.map(|root| root.join(app_name))
+}
+
+/// `path` in one form for comparing paths from different APIs: lower case,
+/// with `/` as `\` and without the `\\?\` prefix, so that 
+/// `\\?\UNC\Server\Share\Apps` and `\\server\share\apps` are equal.
+fn comparable_path(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy().to_lowercase().replace('/', "\\");
+    let text = match text.strip_prefix(r"\\?\unc\") {
+        Some(rest) => format!(r"\\{}", rest),
+        None => text.strip_prefix(r"\\?\").unwrap_or(&text).to_string(),
+    };
+    PathBuf::from(text)
+}
+
+/// Whether `path` is on a share, e.g. `\\server\share\apps`.
+fn is_unc(path: &Path) -> bool {
+    match path.components().next() {
+        Some(Component::Prefix(prefix)) => matches!(prefix.kind(), 
+                Prefix::UNC(..) | Prefix::VerbatimUNC(..)),
+        _ => false,
+    }
+}
+
+/// Why an installation did not complete. The details have already been 
+/// logged by the time one of these is returned.
+#[derive(Debug)]
+pub enum InstallError {
+    AppRunning(String),
+    /// The LOCALAPPDATA known folder could not be found.
+    NoLocalAppData,
+    /// The source has no folder for this app.
+    AppNotFound(String),
+    NoZipFound,
+    CopyFailed,
+    DowngradeCancelled,
+    /// The user chose not to remove the existing install.
+    RemovalCancelled,
+    ExtractFailed,
+    ExeNotFound,
+    InvalidExecutable,
+    NotEnoughSpace,
+    ChecksumMismatch,
+    /// The old version's folder could not be deleted because this file 
+    /// (or, when none could be singled out, the folder) is in use.
+    FileInUse(PathBuf),
+    Cancelled,
+    /// Cancelled by the --timeout watchdog.
+    TimedOut,
+    /// From run_installations: the apps that failed.
+    SomeAppsFailed(Vec<String>),
+    /// --all-users was given to an installer without administrator rights.
+    NotElevated,
+    /// Another installer process is installing or removing this app.
+    InstallInProgress(String),
+}
+
+impl fmt::Display for InstallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallError::AppRunning(app_name) => 
+                    write!(f, "'{}' is running", app_name),
+            InstallError::NoLocalAppData => 
+                    write!(f, "the LOCALAPPDATA folder could not be found"),
+            InstallError::AppNotFound(app_name) => 
+                    write!(f, "there is no source folder for '{}'", app_name),
+            InstallError::NoZipFound => write!(f, "no zip file was found"),
+            InstallError::CopyFailed => write!(f, "the zip could not be copied"),
+            InstallError::DowngradeCancelled => 
+                    write!(f, "the downgrade was cancelled"),
+            InstallError::RemovalCancelled => write!(f, 
+                    "removing the existing installation was cancelled"),
+            InstallError::ExtractFailed => 
+                    write!(f, "the zip could not be extracted"),
+            InstallError::ExeNotFound => 
+                    write!(f, "no executable was found"),
+            InstallError::InvalidExecutable => 
+                    write!(f, "the executable is missing or damaged"),
+            InstallError::NotEnoughSpace => 
+                    write!(f, "there is not enough disk space"),
+            InstallError::ChecksumMismatch => 
+                    write!(f, "the zip's SHA-256 checksum did not match"),
+            InstallError::FileInUse(path) => write!(f, "{:?} is in use", path),
+            InstallError::Cancelled => write!(f, "it was cancelled"),
+            InstallError::TimedOut => write!(f, "it timed out"),
+            InstallError::SomeAppsFailed(app_names) => 
+                    write!(f, "{} failed", app_names.join(", ")),
+            InstallError::NotElevated => write!(f, 
+                    "an all-users install needs administrator rights"),
+            InstallError::InstallInProgress(app_name) => write!(f, 
+                    "another install of '{}' is in progress", app_name),
+        }
+    }
+}
+
+impl std::error::Error for InstallError {}
+
+pub fn run_installation(reporter: &dyn ProgressReporter, 
+        app_name: &str) -> Result<(), InstallError> {
+    let result = with_timeout(|| {
+        check_app_source(reporter, app_name)?;
+        if start_run(reporter)? {
+            return Ok(());
+        }
+        install_app(reporter, app_name)
+    });
+    offer_restart(reporter);
+    result
+}
+
+/// Installs each app in turn, checking for an installer update only once. 
+/// A failed app doesn't stop the rest; cancelling does. With several apps 
+/// none is offered to run afterwards.
+pub fn run_installations(reporter: &dyn ProgressReporter, 
+        app_names: &[String]) -> Result<(), InstallError> {
+    let result = with_timeout(|| install_apps(reporter, app_names));
+    offer_restart(reporter);
+    result
+}
+
+/// The exit code of a --silent install that needs Windows to restart: 
+/// ERROR_SUCCESS_REBOOT_REQUIRED, which deployment tools recognise.
+pub const EXIT_REBOOT_REQUIRED: i32 = 3010;
+
+/// Offers to restart Windows when an app installed in this run needs it. 
+/// Otherwise the restart is left to the user with a warning; --silent, 
+/// which can't ask, then exits with EXIT_REBOOT_REQUIRED.
+fn offer_restart(reporter: &dyn ProgressReporter) {
+    let app_names = REBOOT_REQUIRED.lock().unwrap().join(", ");
+    if app_names.is_empty() || INSTALLER_RESTARTED.load(Ordering::SeqCst) {
+        return;
+    }
+    if reporter.confirm("Restart required", &format!(
+            "{} needs Windows to restart to finish installing. Restart \
+            now?", app_names)) {
+        add_message(reporter, LogLevel::Info, "Restarting Windows...");
+        match Command::new("shutdown.exe").args(["/r", "/t", "0"]).spawn() {
+            Ok(_) => return,
+            Err(e) => add_message(reporter, LogLevel::Error, 
+                    &format!("Failed to restart Windows: {}", e)),
+        }
+    }
+    add_message(reporter, LogLevel::Warn, &format!(
+            "Restart Windows to finish installing {}.", app_names));
+}
+
+fn install_apps(reporter: &dyn ProgressReporter, app_names: &[String]) 
+        -> Result<(), InstallError> {
+    if let [app_name] = app_names {
+        check_app_source(reporter, app_name)?;
+    }
+    if start_run(reporter)? {
+        return Ok(());
+    }
+    if let [app_name] = app_names {
+        return install_app(reporter, app_name);
+    }
+
+    let mut failed = Vec::new();
+    for (i, app_name) in app_names.iter().enumerate() {
+        let label = format!("App {} of {} ({})", i + 1, app_names.len(), 
+                app_name);
+        add_message(reporter, LogLevel::Info, &format!(
+                "Installing app {} of {}: {}", i + 1, app_names.len(), 
+                app_name));
+        let app_reporter = LabelledReporter { reporter, label };
+        match install_app(&app_reporter, app_name) {
+            Ok(()) => {}
+            Err(InstallError::Cancelled) => return Err(InstallError::Cancelled),
+            Err(_) => failed.push(app_name.clone()),
+        }
+    }
+    *EXE_PATH_TO_RUN.lock().unwrap() = None;
+
+    let installed = app_names.len() - failed.len();
+    if failed.is_empty() {
+        add_message(reporter, LogLevel::Info, &format!(
+                "Installed all {} apps.", installed));
+        Ok(())
+    } else {
+        add_message(reporter, LogLevel::Error, &format!(
+                "Installed {} of {} apps. Failed: {}", installed, 
+                app_names.len(), failed.join(", ")));
+        Err(InstallError::SomeAppsFailed(failed))
+    }
+}
+
+/// Runs `install` under the --timeout watchdog, which cancels it once the 
+/// time is up. The cancelled install cleans up as after Cancel, and its 
+/// Cancelled error becomes TimedOut.
+fn with_timeout(install: impl FnOnce() -> Result<(), InstallError>) 
+        -> Result<(), InstallError> {
+    let timeout = match *INSTALL_TIMEOUT.lock().unwrap() {
+        Some(timeout) => timeout,
+        None => return install(),
+    };
+    TIMED_OUT.store(false, Ordering::SeqCst);
+    // Dropping `finished` once the install returns stops the watchdog.
+    let (finished, watchdog) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        if let Err(mpsc::RecvTimeoutError::Timeout) = 
+                watchdog.recv_timeout(timeout) {
+            TIMED_OUT.store(true, Ordering::SeqCst);
+            CANCEL_REQUESTED.store(true, Ordering::SeqCst);
+        }
+    });
+    let result = install();
+    drop(finished);
+    match result {
+        Err(InstallError::Cancelled) if TIMED_OUT.load(Ordering::SeqCst) => 
+                Err(InstallError::TimedOut),
+        result => result,
+    }
+}
+
+/// Puts "App 2 of 5 (Name): " in front of the progress status, so a batch 
+/// shows which app the bar is for.
+struct LabelledReporter<'a> {
+    reporter: &'a dyn ProgressReporter,
+    label: String,
+}
+
+impl ProgressReporter for LabelledReporter<'_> {
+    fn message(&self, message_type: &str, time_str: &str, message: &str) {
+        self.reporter.message(message_type, time_str, message);
+    }
+
+    fn progress(&self, progress: u32, status: &str) {
+        self.reporter.progress(progress, &format!("{}: {}", self.label, 
+                status));
+    }
+
+    fn busy(&self, status: &str) {
+        self.reporter.busy(&format!("{}: {}", self.label, status));
+    }
+
+    fn confirm(&self, title: &str, prompt: &str) -> bool {
+        self.reporter.confirm(title, prompt)
+    }
+
+    fn entry_progress(&self, entry_name: &str, bytes_done: u64, 
+            bytes_total: u64) {
+        self.reporter.entry_progress(entry_name, bytes_done, bytes_total);
+    }
+}
+
+/// Maps a step's 0-100% onto the part of the bar from `start` to `end`, so 
+/// an install's copy and extraction fill it once between them.
+struct ProgressSpan<'a> {
+    reporter: &'a dyn ProgressReporter,
+    start: u32,
+    end: u32,
+}
+
+impl ProgressReporter for ProgressSpan<'_> {
+    fn message(&self, message_type: &str, time_str: &str, message: &str) {
+        self.reporter.message(message_type, time_str, message);
+    }
+
+    fn progress(&self, progress: u32, status: &str) {
+        self.reporter.progress(self.start + progress.min(100) * 
+                (self.end - self.start) / 100, status);
+    }
+
+    fn busy(&self, status: &str) {
+        self.reporter.busy(status);
+    }
+
+    fn confirm(&self, title: &str, prompt: &str) -> bool {
+        self.reporter.confirm(title, prompt)
+    }
+
+    fn entry_progress(&self, entry_name: &str, bytes_done: u64, 
+            bytes_total: u64) {
+        self.reporter.entry_progress(entry_name, bytes_done, bytes_total);
+    }
+}
+
+/// Resets cancellation, makes sure LOCALAPPDATA exists, tidies up after an 
+/// earlier self-update and checks for a newer installer. Returns true if the
+/// updated installer has been started and this run should end.
+fn start_run(reporter: &dyn ProgressReporter) -> Result<bool, InstallError> {
+    CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+    REBOOT_REQUIRED.lock().unwrap().clear();
+    check_elevation(reporter)?;
+    // Every step needs LOCALAPPDATA, so its absence is reported just once.
+    if get_local_appdata_root().is_none() {
+        add_message(reporter, LogLevel::Error, 
+                "Could not find the LOCALAPPDATA folder.");
+        return Err(InstallError::NoLocalAppData);
+    }
+    remove_old_installer(reporter);
+    if *SKIP_SELF_UPDATE.lock().unwrap() || dry_run() {
+        add_message(reporter, LogLevel::Debug, 
+                "Skipping installer self-update.");
+        Ok(false)
+    } else {
+        Ok(update_installer(reporter))
+    }
+}
+
+fn install_app(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Result<(), InstallError> {
+    add_message(reporter, LogLevel::Info, &format!(
+            "Starting installation for {}", app_name));
+    let _app_lock = lock_app(reporter, app_name)?;
+    check_app_source(reporter, app_name)?;
+    let manifest = load_manifest(reporter, app_name);
+
+    if let Some(exe_path) = up_to_date_exe(reporter, app_name, &manifest) {
+        *EXE_PATH_TO_RUN.lock().unwrap() = Some(exe_path);
+        add_message(reporter, LogLevel::Info, "Installation process finished.");
+        return Ok(());
+    }
+
+    close_running_processes(reporter, app_name)?;
+
+    if zip_from_stdin(app_name) {
+        let result = read_zip_from_stdin(reporter, app_name)
+            .and_then(|zip_path| {
+                let result = install_from_zip(reporter, &zip_path, app_name, 
+                        &manifest, 0);
+                if let Err(e) = fs::remove_file(&zip_path) {
+                    add_message(reporter, LogLevel::Warn, &format!(
+                            "Failed to delete temporary zip file: {}", e));
+                }
+                result
+            });
+        return finish_installation(reporter, app_name, result);
+    }
+
+    if let Some(zip_path) = local_zip(app_name) {
+        let result = check_local_zip(reporter, &zip_path).and_then(|()| 
+                install_from_zip(reporter, &zip_path, app_name, &manifest, 
+                    0));
+        return finish_installation(reporter, app_name, result);
+    }
+
+    if let Some(base_url) = remote_url().filter(|_| dry_run()) {
+        add_message(reporter, LogLevel::Info, &format!(
+                "{}Would download {}/{}/latest.zip; a URL source can't be \
+                inspected without downloading it.", DRY_RUN_PREFIX, base_url,
+                app_source_path(app_name)));
+        add_message(reporter, LogLevel::Info, "Installation process finished.");
+        return Ok(());
+    }
+
+    let result = match copy_latest_zip(reporter, app_name) {
+        Ok((zip_path, extract_from, copied)) => {
+            let result = install_from_zip(reporter, &zip_path, app_name, 
+                    &manifest, extract_from);
+            // A dry run, or a source that is the Utils folder, reads the 
+            // source zip in place.
+            if !copied {
+                return finish_installation(reporter, app_name, result);
+            }
+            if let Err(e) = fs::remove_file(&zip_path) {
+                add_message(reporter, LogLevel::Warn,
+                    &format!("Failed to delete temporary zip file: {}", e),
+                );
+            }
+            result
+        }
+        Err(e) => Err(e),
+    };
+    finish_installation(reporter, app_name, result)
+}
+
+/// Removes an installed app (its Start Menu and Desktop shortcuts and its 
+/// folder, user data included) without copying or extracting anything.
+pub fn run_uninstall(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Result<(), InstallError> {
+    CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+    add_message(reporter, LogLevel::Info, 
+            &format!("Uninstalling {}", app_name));
+    check_elevation(reporter)?;
+    let _app_lock = lock_app(reporter, app_name)?;
+    if let Err(e) = close_running_processes(reporter, app_name) {
+        if matches!(e, InstallError::Cancelled) {
+            add_message(reporter, LogLevel::Warn, "Uninstall cancelled.");
+        }
+        return Err(e);
+    }
+    let manifest = load_manifest(reporter, app_name);
+    let (shortcuts, dirs) = uninstall_application(reporter, app_name, 
+            &manifest, false)?;
+    *EXE_PATH_TO_RUN.lock().unwrap() = None;
+    if shortcuts == 0 && dirs == 0 {
+        add_message(reporter, LogLevel::Warn, &format!(
+                "{} does not appear to be installed. Nothing was removed.", 
+                app_name));
+    } else {
+        add_message(reporter, LogLevel::Info, &format!(
+                "{}Uninstalled {}: {} shortcut(s) and {} folder(s) removed.", 
+                if dry_run() { DRY_RUN_PREFIX } else { "" }, app_name, 
+                shortcuts, dirs));
+    }
+    Ok(())
+}
+
+fn finish_installation(reporter: &dyn ProgressReporter, app_name: &str, 
+        result: Result<(), InstallError>) -> Result<(), InstallError> {
+    match &result {
+        Err(InstallError::Cancelled) if TIMED_OUT.load(Ordering::SeqCst) => 
+                add_message(reporter, LogLevel::Error, &format!(
+                    "Installation timed out after {}s.", 
+                    INSTALL_TIMEOUT.lock().unwrap().unwrap_or_default()
+                        .as_secs())),
+        Err(InstallError::Cancelled) => add_message(reporter, LogLevel::Warn, 
+                "Installation cancelled."),
+        Err(e) => add_message(reporter, LogLevel::Error, 
+                &format!("Installation failed for {}: {}.", app_name, e)),
+        Ok(_) => {}
+    }
+    add_message(reporter, LogLevel::Info, "Installation process finished.");
+    result
+}
+
+/// Replaces the installed app with the contents of `zip_path`. The 
+/// extraction's progress starts at `extract_from` percent, the end of the 
+/// copy's share of the bar.
+fn install_from_zip(reporter: &dyn ProgressReporter, zip_path: &Path, 
+        app_name: &str, manifest: &AppManifest, extract_from: u32) 
+        -> Result<(), InstallError> {
+    if dry_run() {
+        add_message(reporter, LogLevel::Debug, 
+                "Skipping the version check, which extracts the exe.");
+    } else if !confirm_version_change(reporter, zip_path, app_name) {
+        return Err(InstallError::DowngradeCancelled);
+    }
+
+    // Cancel may have been pressed (or the timeout passed) while a prompt 
+    // was waiting for an answer.
+    if is_cancelled() {
+        return Err(InstallError::Cancelled);
+    }
+    check_disk_space(reporter, zip_path, app_name)?;
+    if !confirm_removal(reporter, app_name, manifest) {
+        return Err(InstallError::RemovalCancelled);
+    }
+    if is_cancelled() {
+        return Err(InstallError::Cancelled);
+    }
+    if let Err(e) = uninstall_application(reporter, app_name, manifest, true) {
+        roll_back(reporter, app_name, manifest);
+        restore_user_data(reporter, app_name, manifest);
+        return Err(e);
+    }
+
+    let app_dir = get_app_dir(reporter, app_name)
+        .ok_or(InstallError::ExeNotFound)?;
+    let extract_reporter = ProgressSpan { reporter, start: extract_from, 
+            end: 100 };
+    let unzipped = unzip_file(&extract_reporter, zip_path, app_name);
+    let cancelled = !unzipped && is_cancelled();
+    // The exe is found before the user data goes back into the new folder, 
+    // so a rollback deletes nothing but what the zip brought.
+    let exe_path = if unzipped && !dry_run() {
+        main_executable(reporter, &app_dir, app_name, manifest)
+    } else {
+        None
+    };
+    // A truncated or non-PE exe is as good as none: the previous version 
+    // comes back rather than being deleted.
+    let exe_error = exe_path.as_deref()
+        .and_then(|exe_path| verify_executable(exe_path).err());
+    let rolled_back = !dry_run() && 
+            (!unzipped || exe_path.is_none() || exe_error.is_some()) && 
+            roll_back(reporter, app_name, manifest);
+    if cancelled && !rolled_back {
+        remove_partial_install(reporter, app_name);
+    }
+    // Kept user data goes back even when the extraction failed.
+    restore_user_data(reporter, app_name, manifest);
+    if cancelled {
+        return Err(InstallError::Cancelled);
+    } else if !unzipped {
+        return Err(InstallError::ExtractFailed);
+    }
+
+    if dry_run() {
+        return dry_run_shortcut(reporter, zip_path, &app_dir, app_name, 
+                manifest);
+    }
+    let exe_path = match exe_path {
+        Some(exe_path) => exe_path,
+        None => {
+            add_message(reporter, LogLevel::Error,
+                &format!("Could not find executable for {}", app_name),
+            );
+            return Err(InstallError::ExeNotFound);
+        }
+    };
+    // A broken exe must not be left behind a shortcut.
+    if let Some(e) = exe_error {
+        add_message(reporter, LogLevel::Error, &format!(
+                "{:?} is not a valid executable: {}", exe_path, e));
+        return Err(InstallError::InvalidExecutable);
+    }
+    discard_previous_install(reporter, app_name);
+    report_install_size(reporter, &app_dir);
+    add_message(reporter, LogLevel::Debug, 
+            &format!("Found executable at {:?}", exe_path));
+    match exe_path.to_str() {
+        Some(exe_str) => {
+            let display_name = create_app_shortcuts(reporter, &app_dir, 
+                    &exe_path, exe_str, app_name, manifest);
+            write_installed_version(reporter, &app_dir, zip_path);
+            if *REGISTER_UNINSTALL.lock().unwrap() {
+                register_uninstall_entry(reporter, app_name, &app_dir, 
+                        &exe_path, &display_name, zip_path);
+            }
+            *EXE_PATH_TO_RUN.lock().unwrap() = Some(exe_path.clone());
+            if manifest.reboot_required {
+                REBOOT_REQUIRED.lock().unwrap().push(app_name.to_string());
+            }
+            Ok(())
+        }
+        None => {
+            add_message(reporter, LogLevel::Error,
+                "Executable path contains invalid characters.");
+            Err(InstallError::ExeNotFound)
+        }
+    }
+}
+
+/// Reports the shortcut a real install would create, for the exe that 
+/// find_executable would pick once the zip is extracted.
+fn dry_run_shortcut(reporter: &dyn ProgressReporter, zip_path: &Path, 
+        app_dir: &Path, app_name: &str, manifest: &AppManifest) 
+        -> Result<(), InstallError> {
+    let exe_name = manifest.main_exe.as_ref()
+        .map(|main_exe| main_exe.replace('/', "\\"))
+        .filter(|main_exe| 
+                zip_utils::join_entry_path(app_dir, main_exe).is_ok())
+        .or_else(|| File::open(zip_path).ok()
+        .map(BufReader::new)
+        .and_then(|mut reader| 
+                zip_utils::read_central_directory_keep_first(&mut reader).ok())
+        .and_then(|(entries, _)| zip_main_exe(&entries, app_name)
+                .map(|entry| entry.file_name.replace('/', "\\"))));
+    let exe_path = match exe_name {
+        Some(exe_name) => app_dir.join(exe_name),
+        None => {
+            add_message(reporter, LogLevel::Error,
+                &format!("Could not find executable for {}", app_name));
+            return Err(InstallError::ExeNotFound);
+        }
+    };
+    let display_name = manifest.display_name.as_deref()
+        .map(shortcut_file_name)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| add_spaces(app_name));
+    let arguments = shortcut_arguments(manifest);
+    let working_dir = SHORTCUT_WORKDIR.lock().unwrap().as_ref()
+        .map(|dir| app_dir.join(dir));
+    create_shortcut(reporter, &exe_path.to_string_lossy(), &display_name, 
+            arguments.as_deref(), working_dir.as_deref(), None,
+            desktop_shortcut(manifest));
+    create_extra_shortcuts(reporter, app_dir, manifest);
+    Ok(())
+}
+
+/// Creates the Start Menu (and, if asked for, Desktop) shortcut to the exe 
+/// and returns its name.
+fn create_app_shortcuts(reporter: &dyn ProgressReporter, app_dir: &Path, 
+        exe_path: &Path, exe_str: &str, app_name: &str, manifest: &AppManifest)
+        -> String {
+    let display_name = shortcut_display_name(exe_path, app_name, manifest);
+    let arguments = shortcut_arguments(manifest);
+    let working_dir = SHORTCUT_WORKDIR.lock().unwrap().as_ref()
+        .map(|dir| app_dir.join(dir));
+    let icon = shortcut_icon(reporter, app_dir, exe_path, manifest);
+    create_shortcut(reporter, exe_str, &display_name, arguments.as_deref(), 
+            working_dir.as_deref(), icon.as_deref(), 
+            desktop_shortcut(manifest));
+    create_extra_shortcuts(reporter, app_dir, manifest);
+    display_name
+}
+
+/// Creates the manifest's extra shortcuts in the Start Menu, skipping, with
+/// a warning, any whose target the zip didn't contain or that points out of
+/// the app folder.
+fn create_extra_shortcuts(reporter: &dyn ProgressReporter, app_dir: &Path, 
+        manifest: &AppManifest) {
+    let working_dir = SHORTCUT_WORKDIR.lock().unwrap().as_ref()
+        .map(|dir| app_dir.join(dir));
+    for shortcut in &manifest.shortcuts {
+        let name = shortcut_file_name(&shortcut.name);
+        let target = zip_utils::join_entry_path(app_dir, 
+                &shortcut.target.replace('/', "\\")).ok()
+            .filter(|target| dry_run() || target.is_file());
+        let target = match target {
+            Some(target) if !name.is_empty() => target,
+            _ => {
+                add_message(reporter, LogLevel::Warn, &format!(
+                        "Skipping shortcut {:?}: {:?} is not a file in {:?}.",
+                        shortcut.name, shortcut.target, app_dir));
+                continue;
+            }
+        };
+        let icon = shortcut.icon.as_ref()
+            .and_then(|icon| zip_utils::join_entry_path(app_dir, 
+                    &icon.replace('/', "\\")).ok())
+            .filter(|icon| icon.is_file());
+        create_shortcut(reporter, &target.to_string_lossy(), &name, 
+                shortcut.args.as_deref(), working_dir.as_deref(), 
+                icon.as_deref(), false);
+        add_message(reporter, LogLevel::Info, &format!(
+                "Created shortcut {} to {:?}", name, target));
+    }
+}
+
+/// Where the previous version waits while the new one is extracted. Like 
+/// the user data stash it sits next to the app folder, so moving the old 
+/// folder there is a rename.
+fn previous_install_dir(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Option<PathBuf> {
+    get_app_dir(reporter, app_name)
+        .map(|app_dir| app_dir.with_file_name(
+                format!("{}.previous", app_name)))
+}
+
+/// Puts the previous version back after a failed update: deletes what the 
+/// extraction left, moves the old folder back and recreates its shortcuts.
+/// Returns false, leaving things as they are, when there is no previous 
+/// version to go back to. The caller still restores the kept user data.
+fn roll_back(reporter: &dyn ProgressReporter, app_name: &str, 
+        manifest: &AppManifest) -> bool {
+    let (app_dir, previous_dir) = match (get_app_dir(reporter, app_name), 
+            previous_install_dir(reporter, app_name)) {
+        (Some(app_dir), Some(previous_dir)) if previous_dir.is_dir() => 
+                (app_dir, previous_dir),
+        _ => return false,
+    };
+    let cleared = if app_dir.exists() {
+        fs::remove_dir_all(&app_dir)
+    } else {
+        Ok(())
+    };
+    if let Err(e) = cleared {
+        add_message(reporter, LogLevel::Error, &format!(
+                "Failed to delete the partial install {:?}: {}. The \
+                previous version is in {:?}.", app_dir, e, previous_dir));
+        return false;
+    }
+    if let Err(e) = fs::rename(&previous_dir, &app_dir) {
+        add_message(reporter, LogLevel::Error, &format!(
+                "Failed to restore the previous version from {:?}: {}", 
+                previous_dir, e));
+        return false;
+    }
+    let exe_path = main_executable(reporter, &app_dir, app_name, manifest);
+    if let Some(exe_str) = exe_path.as_deref().and_then(Path::to_str) {
+        create_app_shortcuts(reporter, &app_dir, Path::new(exe_str), exe_str, 
+                app_name, manifest);
+    }
+    add_message(reporter, LogLevel::Warn, &format!(
+            "The update failed, but the previous version of {} is intact.", 
+            app_name));
+    true
+}
+
+/// Deletes the previous version once the new one has been extracted.
+fn discard_previous_install(reporter: &dyn ProgressReporter, app_name: &str) {
+    let previous_dir = match previous_install_dir(reporter, app_name) {
+        Some(previous_dir) if previous_dir.exists() => previous_dir,
+        _ => return,
+    };
+    match remove_dir_with_retries(&previous_dir) {
+        Ok(()) => add_message(reporter, LogLevel::Debug, &format!(
+                "Deleted the previous version at {:?}", previous_dir)),
+        Err((e, _)) => add_message(reporter, LogLevel::Warn, &format!(
+                "Failed to delete the previous version at {:?}: {}", 
+                previous_dir, e)),
+    }
+}
+
+/// Deletes whatever a cancelled extraction left in the app folder.
+fn remove_partial_install(reporter: &dyn ProgressReporter, app_name: &str) {
+    if let Some(app_dir) = get_app_dir(reporter, app_name) && 
+            let Err(e) = fs::remove_dir_all(&app_dir) {
+        add_message(reporter, LogLevel::Warn, &format!(
+                "Failed to delete partial install {:?}: {}", app_dir, e));
+    }
+}
+
+const VERSION_FILE_NAME: &str = ".version";
+
+/// Returns the installed exe when the app folder's .version file matches the
+/// version of the newest zip in the source, meaning there is nothing to do.
+/// Zips without a version in their name, URL sources and a --zip file are 
+/// always installed.
+fn up_to_date_exe(reporter: &dyn ProgressReporter, app_name: &str, 
+        manifest: &AppManifest) -> Option<PathBuf> {
+    if remote_url().is_some() || zip_given(app_name) {
+        return None;
+    }
+    let source_dir_path = app_source_dir(app_name);
+    let (newest_zip, _) = find_zip_to_install(&source_dir_path, app_name)
+        .ok()??;
+    let latest_version = zip_version(&newest_zip)?.to_string();
+
+    let app_dir = get_app_dir(reporter, app_name)?;
+    let installed_version = fs::read_to_string(
+            app_dir.join(VERSION_FILE_NAME)).ok()?;
+    if installed_version.trim() != latest_version {
+        add_message(reporter, LogLevel::Debug, &format!(
+                "Installed version {} differs from latest {}", 
+                installed_version.trim(), latest_version));
+        return None;
+    }
+    let exe_path = main_executable(reporter, &app_dir, app_name, manifest)?;
+    if let Err(e) = verify_executable(&exe_path) {
+        add_message(reporter, LogLevel::Debug, &format!(
+                "Installed {:?} is not a valid executable: {}", exe_path, e));
+        return None;
+    }
+    add_message(reporter, LogLevel::Info, &format!(
+            "{} is already up to date (version {}).", app_name, 
+            latest_version));
+    Some(exe_path)
+}
+
+/// Records the installed zip's version in the app folder so the next run can
+/// skip an unchanged install.
+fn write_installed_version(reporter: &dyn ProgressReporter, app_dir: &Path, 
+        zip_path: &Path) {
+    if let Some(version) = zip_version(zip_path) {
+        let version_path = app_dir.join(VERSION_FILE_NAME);
+        if let Err(e) = fs::write(&version_path, version.to_string()) {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Failed to write {:?}: {}", version_path, e));
+        }
+    }
+}
+
+/// Compares the FileVersion of the exe inside the new zip with the installed
+/// one. A downgrade is logged and only goes ahead with --allow-downgrade or
+/// the user's confirmation. Returns false if the install should stop.
+fn confirm_version_change(reporter: &dyn ProgressReporter, zip_path: &Path, 
+        app_name: &str) -> bool {
+    let installed_version = get_app_dir(reporter, app_name)
+        .and_then(|app_dir| find_executable(&app_dir, app_name))
+        .and_then(|exe_path| version_utils::file_version(&exe_path));
+    let installed_version = match installed_version {
+        Some(version) => version,
+        None => return true,
+    };
+    let new_version = match zip_exe_version(zip_path, app_name) {
+        Some(version) => version,
+        None => {
+            add_message(reporter, LogLevel::Debug, 
+                    "Could not read the new executable's version.");
+            return true;
+        }
+    };
+    add_message(reporter, LogLevel::Debug, &format!(
+            "Installed version {}, new version {}", installed_version, 
+            new_version));
+    if new_version >= installed_version {
+        return true;
+    }
+
+    add_message(reporter, LogLevel::Warn, &format!(
+            "The new version {} is older than the installed version {}.",
+            new_version, installed_version));
+    if *ALLOW_DOWNGRADE.lock().unwrap() {
+        return true;
+    }
+    let prompt = format!(
+            "This will replace {} {} with the older version {}. Continue?",
+            app_name, installed_version, new_version);
+    if reporter.confirm("App Installer", &prompt) {
+        true
+    } else {
+        add_message(reporter, LogLevel::Error, 
+                &format!("Downgrade of {} cancelled.", app_name));
+        false
+    }
+}
+
+/// With CONFIRM_REMOVAL, asks before an existing install of `app_name` is
+/// removed. Returns false if the user said no.
+fn confirm_removal(reporter: &dyn ProgressReporter, app_name: &str, 
+        manifest: &AppManifest) -> bool {
+    if !*CONFIRM_REMOVAL.lock().unwrap() || dry_run() || 
+            app_name == "AppInstaller" {
+        return true;
+    }
+    let app_dir = match get_app_dir(reporter, app_name)
+            .filter(|app_dir| app_dir.exists()) {
+        Some(app_dir) => app_dir,
+        None => return true,
+    };
+    let name = manifest.display_name.as_deref().unwrap_or(app_name);
+    let prompt = format!(
+            "This will remove the existing installation of {} in {}. \
+            Continue?", name, app_dir.display());
+    if reporter.confirm("App Installer", &prompt) {
+        true
+    } else {
+        add_message(reporter, LogLevel::Error, &format!(
+                "Removal of the existing {} installation cancelled.", 
+                app_name));
+        false
+    }
+}
+
+/// The zip entry find_executable would choose once the zip is extracted.
+fn zip_main_exe<'a>(entries: &'a [zip_utils::ZipEntry], app_name: &str) 
+        -> Option<&'a zip_utils::ZipEntry> {
+    let names = [app_name.to_lowercase(), add_spaces(app_name).to_lowercase()];
+    entries.iter()
+        .filter(|entry| entry.file_name.matches('/').count() <= 1 && 
+                entry.file_name.to_lowercase().ends_with(".exe"))
+        .min_by_key(|entry| {
+            let file_name = entry.file_name.rsplit('/').next().unwrap_or("")
+                .to_lowercase();
+            let name_matches = names.iter()
+                .any(|name| file_name == format!("{}.exe", name));
+            (!name_matches, std::cmp::Reverse(entry.uncompressed_size), 
+                    entry.file_name.clone())
+        })
+}
+
+/// Reads the FileVersion of the zip's main exe, chosen as find_executable
+/// would, by extracting just that entry to a temporary directory.
+fn zip_exe_version(zip_path: &Path, app_name: &str) 
+        -> Option<version_utils::FileVersion> {
+    let mut reader = BufReader::new(File::open(zip_path).ok()?);
+    let (entries, _) = zip_utils::read_central_directory_keep_first(
+            &mut reader).ok()?;
+    let entry = zip_main_exe(&entries, app_name)?;
+    let temp_dir = env::temp_dir().join(format!("AppInstaller-{}", 
+            std::process::id()));
+    let version = match zip_utils::extract_file(entry, &mut reader, 
+            &temp_dir) {
+        Ok(path) => version_utils::file_version(&path),
+        Err(_) => None,
+    };
+    let _ = fs::remove_dir_all(&temp_dir);
+    version
+}
+
+/// With --dry-run every step that would change the disk logs what it would
+/// do, prefixed with DRY_RUN_PREFIX, instead.
+fn dry_run() -> bool {
+    *DRY_RUN.lock().unwrap()
+}
+
+const DRY_RUN_PREFIX: &str = "[DRY RUN] ";
+
+fn is_cancelled() -> bool {
+    CANCEL_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Logs a row to the log file, and to the reporter (and JSON stdout) when 
+/// `level` is within --log-level.
+pub fn add_message(reporter: &dyn ProgressReporter, level: LogLevel, 
+        message: &str) {
+    let now = Local::now();
+    let message_type = level.as_str();
+    let time_str = now.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+    append_to_log_file(&format!("{} {:<5} {}", time_str, message_type, 
+            message));
+    if level > *LOG_LEVEL.lock().unwrap() {
+        return;
+    }
+    if *LOG_FORMAT.lock().unwrap() == LogFormat::Json {
+        println!("{}", serde_json::json!({
+            "level": message_type,
+            "ts": now.format("%Y-%m-%dT%H:%M:%S%.3f%:z").to_string(),
+            "msg": message,
+        }));
+    }
+    reporter.message(message_type, &time_str, message);
+}
+
+const LOG_FILE_NAME: &str = "AppInstaller.log";
+const MAX_LOG_FILE_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Appends a line to AppInstaller.log in the Utils directory, DEBUG and 
+/// TRACE rows included. Once the file passes MAX_LOG_FILE_SIZE it is moved to 
+/// AppInstaller.log.1, replacing the previous one. Failures are ignored since
+/// there is nowhere left to report them.
+fn append_to_log_file(line: &str) {
+    let log_dir = match staging_dir() {
+        Some(log_dir) => log_dir,
+        None => return,
+    };
+    if fs::create_dir_all(&log_dir).is_err() {
+        return;
+    }
+    let log_path = log_dir.join(LOG_FILE_NAME);
+    if fs::metadata(&log_path).map(|m| m.len() > MAX_LOG_FILE_SIZE)
+            .unwrap_or(false) {
+        let _ = fs::rename(&log_path, 
+                log_dir.join(format!("{}.1", LOG_FILE_NAME)));
+    }
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true)
+            .open(&log_path) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// Added to the running installer's name while a self-update replaces it.
+const OLD_INSTALLER_EXTENSION: &str = "AppInstaller.old";
+
+/// A named Windows mutex held until dropped. Used to serialize work that 
+/// several installer processes could otherwise race on.
+struct NamedMutex {
+    handle: HANDLE,
+}
+
+impl NamedMutex {
+    fn acquire(name: &str, timeout_ms: u32) -> Option<NamedMutex> {
+        let wide_name: Vec<u16> = std::ffi::OsStr::new(name).encode_wide()
+                .chain(Some(0)).collect();
+        let handle = unsafe {
+            CreateMutexW(std::ptr::null_mut(), 0, wide_name.as_ptr())
+        };
+        if handle.is_null() {
+            return None;
+        }
+        let wait = unsafe { WaitForSingleObject(handle, timeout_ms) };
+        if wait == WAIT_OBJECT_0 || wait == WAIT_ABANDONED {
+            Some(NamedMutex { handle })
+        } else {
+            unsafe { CloseHandle(handle); }
+            None
+        }
+    }
+}
+
+impl Drop for NamedMutex {
+    fn drop(&mut self) {
+        unsafe {
+            ReleaseMutex(self.handle);
+            CloseHandle(self.handle);
+        }
+    }
+}
+
+/// Claims `app_name` for this process until the returned lock is dropped, 
+/// so two installers (a double launch, or an MDM retry) never copy into or 
+/// delete the same folder at once. The second one stops straight away 
+/// instead of waiting. Mutex names are case-sensitive and folder names are
+/// not, hence the lower case; all-users installs share one across sessions.
+fn lock_app(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Result<NamedMutex, InstallError> {
+    let namespace = if all_users() { "Global" } else { "Local" };
+    let name = format!(r"{}\AppInstaller.App.{}", namespace, 
+            app_name.to_lowercase());
+    match NamedMutex::acquire(&name, 0) {
+        Some(lock) => Ok(lock),
+        None => {
+            add_message(reporter, LogLevel::Error, &format!(
+                    "Another install of {} is in progress. Let it finish, \
+                    then try again.", app_name));
+            Err(InstallError::InstallInProgress(app_name.to_string()))
+        }
+    }
+}
+
+/// Checks the share for a newer installer and installs it. Returns true if
+/// the new installer has been started and this one should exit.
+fn update_installer(reporter: &dyn ProgressReporter) -> bool {
+    add_message(reporter, LogLevel::Info, "Checking for installer updates...");
+    if remote_url().is_some() {
+        add_message(reporter, LogLevel::Debug, 
+                "Installer self-update is not supported over HTTP.");
+        return false;
+    }
+    // Two launches must not rename and re-download the installer at once.
+    let _update_lock = match NamedMutex::acquire(
+            r"Local\AppInstaller.SelfUpdate", 60_000) {
+        Some(lock) => lock,
+        None => {
+            add_message(reporter, LogLevel::Warn, 
+                "Another installer is updating itself. Skipping self-update.");
+            return false;
+        }
+    };
+    let remote_dir = REMOTE_DIR.lock().unwrap().clone().join("AppInstaller");
+    if !remote_dir.is_dir() {
+        add_message(reporter, LogLevel::Warn, &format!(
+                "Installer source {:?} is unreachable. Skipping self-update.",
+                remote_dir));
+        return false;
+    }
+    if let Some(local_appdata) = get_local_appdata(reporter) {
+        let local_installer_path = local_appdata.join(
+                "AppInstaller").join("AppInstaller.exe");
+        if !local_installer_path.exists() {
+            add_message(reporter, LogLevel::Info, 
+                    "No local installer found. Downloading...");
+            get_installer(reporter);
+            return false;
+        }
+
+        if let Ok(current_exe) = env::current_exe() {
+            // An installer from before the zip time was recorded is updated
+            // once so that it gets one.
+            let installed_time = installer_zip_time(&local_installer_path)
+                .unwrap_or_else(|| {
+                    add_message(reporter, LogLevel::Debug, 
+                            "The installed installer's zip time is unknown.");
+                    0
+                });
+            return perform_installer_update(installed_time, current_exe, 
+                    &local_installer_path, reporter);
+        }
+    }
+    false
+}
+
+/// The file next to the installed installer holding the modified time of 
+/// the zip it came from. The extracted exe's own time is when it was 
+/// extracted, which can't be compared with the zips on the share.
+const INSTALLER_ZIP_TIME_FILE_NAME: &str = ".zip_time";
+
+/// The recorded zip time of the installer at `installer_path`, in seconds 
+/// since the Unix epoch.
+fn installer_zip_time(installer_path: &Path) -> Option<u64> {
+    fs::read_to_string(installer_path.with_file_name(
+            INSTALLER_ZIP_TIME_FILE_NAME)).ok()?.trim().parse().ok()
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn perform_installer_update(installed_time: u64, current_exe: PathBuf, 
+        new_installer: &Path, reporter: &dyn ProgressReporter) -> bool {
+    let remote_dir = REMOTE_DIR.lock().unwrap().clone().join(
+            "AppInstaller");
+    let newest_remote_file = find_newest_zip(&remote_dir).unwrap_or(None);
+
+    if let Some((_, remote_time)) = newest_remote_file {
+        if unix_seconds(remote_time) > installed_time {
+            add_message(reporter, LogLevel::Info, 
+                    "Newer installer found. Updating...");
+            let new_name = current_exe.with_extension(OLD_INSTALLER_EXTENSION);
+            if let Err(e) = fs::rename(&current_exe, &new_name) {
+                add_message(reporter, LogLevel::Error,
+                    &format!("Failed to rename old installer: {}", e),
+                );
+                return false;
+            }
+            if !get_installer(reporter) {
+                restore_installer(reporter, &current_exe, &new_name);
+                return false;
+            }
+            add_message(reporter, LogLevel::Info, "Installer updated.");
+            return restart_installer(reporter, new_installer);
+        }
+    }
+    false
+}
+
+/// Starts the updated installer with this run's arguments. It has only 
+/// just been installed, so it is told to skip the self-update check.
+fn restart_installer(reporter: &dyn ProgressReporter, new_installer: &Path) 
+        -> bool {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if !args.iter().any(|arg| arg == "--no-self-update") {
+        args.push("--no-self-update".to_string());
+    }
+    match Command::new(new_installer).args(&args).spawn() {
+        Ok(_) => {
+            add_message(reporter, LogLevel::Info, 
+                    "Restarting with the updated installer.");
+            INSTALLER_RESTARTED.store(true, Ordering::SeqCst);
+            true
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Failed to start the updated installer {:?}: {}. \
+                    Continuing with this one.", new_installer, e));
+            false
+        }
+    }
+}
+
+/// Deletes the installer renamed aside by a previous self-update. That 
+/// process may still be exiting, so a locked file gets a few more tries.
+fn remove_old_installer(reporter: &dyn ProgressReporter) {
+    let old_exe = match env::current_exe() {
+        Ok(exe) => exe.with_extension(OLD_INSTALLER_EXTENSION),
+        Err(_) => return,
+    };
+    if !old_exe.exists() {
+        return;
+    }
+    for attempt in 0..5 {
+        match fs::remove_file(&old_exe) {
+            Ok(()) => {
+                add_message(reporter, LogLevel::Debug, 
+                        &format!("Removed old installer {:?}", old_exe));
+                return;
+            }
+            Err(e) if attempt == 4 => {
+                add_message(reporter, LogLevel::Debug, &format!(
+                        "Could not remove old installer {:?}: {}", old_exe, e));
+            }
+            Err(_) => thread::sleep(Duration::from_millis(500)),
+        }
+    }
+}
+
+/// Puts the renamed installer back after a failed self-update so the 
+/// machine is never left without a working installer.
+fn restore_installer(reporter: &dyn ProgressReporter, current_exe: &Path, 
+        old_exe: &Path) {
+    if current_exe.exists() {
+        let _ = fs::remove_file(current_exe);
+    }
+    if let Err(e) = fs::rename(old_exe, current_exe) {
+        add_message(reporter, LogLevel::Error,
+            &format!("Failed to restore previous installer: {}", e));
+    } else {
+        add_message(reporter, LogLevel::Warn, 
+                "Installer update failed. Restored previous installer.");
+    }
+}
+
+/// Processes that would keep the install folder locked: `<app_name>.exe`
+/// wherever it runs from, and anything whose executable lives inside the
+/// install folder (helpers and child processes).
+fn find_running_processes(system: &System, app_name: &str, 
+        app_dir: Option<&Path>) -> Vec<Pid> {
+    let process_name = format!("{}.exe", app_name).to_lowercase();
+    let app_dir = app_dir.map(comparable_path);
+    system.processes().iter()
+        .filter(|(_, process)| {
+            process.name().to_lowercase() == process_name || 
+                app_dir.as_ref().is_some_and(|dir| 
+                    comparable_path(process.exe()).starts_with(dir))
+        })
+        .map(|(pid, _)| *pid)
+        .collect()
+}
+
+/// How long listing the processes may take before the check is skipped.
+const PROCESS_LIST_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often the waits for processes look at CANCEL_REQUESTED.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The running processes, with just their names and exe paths rather than 
+/// CPU, disk and user details. The listing runs on its own thread so that a
+/// hung one can't stall the install: after PROCESS_LIST_TIMEOUT it is 
+/// abandoned with a warning and None is returned. Anything still running 
+/// will then show up as a locked file. Cancel abandons it too, and the 
+/// caller checks is_cancelled.
+fn list_processes(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Option<System> {
+    reporter.busy(&format!("Checking whether {} is running", app_name));
+    let started = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(System::new_with_specifics(RefreshKind::new()
+                .with_processes(ProcessRefreshKind::new())));
+    });
+    let mut listed = receiver.recv_timeout(CANCEL_POLL_INTERVAL);
+    while listed.is_err() && !is_cancelled() && 
+            started.elapsed() < PROCESS_LIST_TIMEOUT {
+        listed = receiver.recv_timeout(CANCEL_POLL_INTERVAL);
+    }
+    match listed {
+        Ok(system) => {
+            add_message(reporter, LogLevel::Debug, &format!(
+                    "Listed {} processes in {} ms.", system.processes().len(),
+                    started.elapsed().as_millis()));
+            Some(system)
+        }
+        Err(_) if is_cancelled() => None,
+        Err(_) => {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Listing processes took over {}s. Not checking whether \
+                    '{}' is running.", PROCESS_LIST_TIMEOUT.as_secs(), 
+                    app_name));
+            None
+        }
+    }
+}
+
+/// Checks that nothing is running from the app. If something is, the 
+/// processes are listed and the user may close them; without a window (or 
+/// if they decline) the install stops with AppRunning. Cancel stops the 
+/// listing or the wait for the processes to exit with Cancelled.
+fn close_running_processes(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Result<(), InstallError> {
+    let app_dir = get_app_dir(reporter, app_name);
+    let listed = list_processes(reporter, app_name);
+    if is_cancelled() {
+        return Err(InstallError::Cancelled);
+    }
+    let mut system = match listed {
+        Some(system) => system,
+        None => return Ok(()),
+    };
+    let running = find_running_processes(&system, app_name, 
+            app_dir.as_deref());
+    if running.is_empty() {
+        return Ok(());
+    }
+
+    let names: Vec<String> = running.iter()
+        .filter_map(|pid| system.process(*pid))
+        .map(|process| format!("{} ({})", process.name(), process.pid()))
+        .collect();
+    add_message(reporter, LogLevel::Error, &format!(
+            "{} process(es) from '{}' are running: {}", running.len(), 
+            app_name, names.join(", ")));
+    if dry_run() {
+        add_message(reporter, LogLevel::Info, &format!(
+                "{}Would ask to close these processes.", DRY_RUN_PREFIX));
+        return Ok(());
+    }
+    if !reporter.confirm("Close running processes", &format!(
+            "{} is still running:\n\n{}\n\nClose these processes and \
+            continue? Unsaved work in them will be lost.", app_name, 
+            names.join("\n"))) {
+        add_message(reporter, LogLevel::Error, &format!(
+                "'{}' is running. Please close it and try again.", app_name));
+        return Err(InstallError::AppRunning(app_name.to_string()));
+    }
+    if is_cancelled() {
+        return Err(InstallError::Cancelled);
+    }
+
+    for pid in &running {
+        if let Some(process) = system.process(*pid)
+                .filter(|process| process.kill()) {
+            add_message(reporter, LogLevel::Info, &format!("Closed {} ({})", 
+                    process.name(), pid));
+        }
+    }
+
+    // Give the processes a moment to exit and release their files.
+    for _ in 0..10 {
+        for _ in 0..5 {
+            if is_cancelled() {
+                return Err(InstallError::Cancelled);
+            }
+            thread::sleep(CANCEL_POLL_INTERVAL);
+        }
+        system.refresh_processes_specifics(ProcessRefreshKind::new());
+        if find_running_processes(&system, app_name, app_dir.as_deref())
+                .is_empty() {
+            return Ok(());
+        }
+    }
+    add_message(reporter, LogLevel::Error, &format!(
+            "Some '{}' processes could not be closed.", app_name));
+    Err(InstallError::AppRunning(app_name.to_string()))
+}
+
+/// Deletes the app's shortcuts and folder. When `replacing` it with a new 
+/// version, the manifest's user data is set aside for restore_user_data 
+/// first and the app folder is moved to previous_install_dir rather than 
+/// deleted, for roll_back. Returns how many shortcuts and folders were (or,
+/// in a dry run, would be) removed, or FileInUse if a folder stays locked, 
+/// so nothing is extracted over a half-deleted one.
+fn uninstall_application(reporter: &dyn ProgressReporter, app_name: &str, 
+        manifest: &AppManifest, replacing: bool) 
+        -> Result<(usize, usize), InstallError> {
+    add_message(reporter, LogLevel::Debug,
+        &format!("Attempting to uninstall application: {}", app_name));
+    reporter.busy(&format!("Removing {}", app_name));
+    // The shortcut may be named by the manifest, after the installed exe's 
+    // product name or, for older installs, the add_spaces form of the app 
+    // name (or what add_spaces used to make of it).
+    let mut shortcut_names = vec![add_spaces(app_name)];
+    if legacy_add_spaces(app_name) != shortcut_names[0] {
+        shortcut_names.push(legacy_add_spaces(app_name));
+    }
+    if let Some(app_dir) = get_app_dir(reporter, app_name) && 
+            let Some(exe_path) = main_executable(reporter, &app_dir, 
+                app_name, manifest) {
+        let display_name = shortcut_display_name(&exe_path, app_name, 
+                manifest);
+        if !shortcut_names.contains(&display_name) {
+            shortcut_names.push(display_name);
+        }
+    }
+    // The manifest's extra shortcuts go too, but their targets needn't be 
+    // in the app folder itself, so they don't say which folder to delete.
+    let extra_names: Vec<String> = manifest.shortcuts.iter()
+        .map(|shortcut| shortcut_file_name(&shortcut.name))
+        .filter(|name| !name.is_empty() && !shortcut_names.contains(name))
+        .collect();
+    let shortcuts: Vec<(PathBuf, Option<PathBuf>)> = shortcut_names.iter()
+        .flat_map(|name| find_shortcuts(name))
+        .chain(extra_names.iter()
+            .flat_map(|name| find_shortcuts(name))
+            .map(|(shortcut_path, _)| (shortcut_path, None)))
+        .collect();
+    let roots: Vec<PathBuf> = install_root(reporter).into_iter()
+        .chain(get_local_appdata(reporter))
+        .chain(all_users_root())
+        .collect();
+    let mut target_dirs: Vec<PathBuf> = Vec::new();
+    let (mut shortcuts_removed, mut dirs_removed) = (0, 0);
+    for (shortcut_path, target_dir) in shortcuts {
+        // Without --all-users an all-users install is left alone; it needs
+        // an elevated installer to change.
+        if !all_users() && is_all_users_path(&shortcut_path) {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Leaving {:?}, which belongs to an all-users install of \
+                    {}; use --all-users to change that one.", shortcut_path, 
+                    app_name));
+            continue;
+        }
+        if let Some(target_dir) = target_dir
+                .and_then(|target_dir| shortcut_app_dir(target_dir, &roots))
+                .filter(|target_dir| !target_dirs.contains(target_dir)) {
+            target_dirs.push(target_dir);
+        }
+        if remove_shortcut(reporter, &shortcut_path) {
+            shortcuts_removed += 1;
+        }
+    }
+
+    remove_uninstall_entry(reporter, app_name);
+
+    if target_dirs.is_empty() {
+        add_message(reporter, LogLevel::Debug, 
+                "No existing shortcut found. Checking default location.");
+        target_dirs.extend(get_app_dir(reporter, app_name));
+    }
+
+    let keep_patterns = manifest.keep_user_data.clone().unwrap_or_else(|| 
+            DEFAULT_KEEP_USER_DATA.iter().map(|p| p.to_string()).collect());
+    let stash_dir = user_data_stash(reporter, app_name)
+        .filter(|_| replacing);
+    let previous = get_app_dir(reporter, app_name)
+        .zip(previous_install_dir(reporter, app_name))
+        .filter(|_| replacing);
+    for target_dir in target_dirs {
+        if !target_dir.exists() {
+            continue;
+        }
+        if !all_users() && is_all_users_path(&target_dir) {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Leaving {:?}, which belongs to an all-users install of \
+                    {}; use --all-users to change that one.", target_dir, 
+                    app_name));
+            continue;
+        }
+        if let Some(stash_dir) = &stash_dir {
+            stash_user_data(reporter, &target_dir, Path::new(""), 
+                    stash_dir, &keep_patterns);
+        }
+        if dry_run() {
+            add_message(reporter, LogLevel::Info, &format!(
+                    "{}Would delete existing directory at {:?}", 
+                    DRY_RUN_PREFIX, target_dir));
+            dirs_removed += 1;
+            continue;
+        }
+        let removed = match &previous {
+            Some((app_dir, previous_dir)) if *app_dir == target_dir => 
+                    set_aside_install(reporter, &target_dir, previous_dir),
+            _ => remove_dir_with_retries(&target_dir),
+        };
+        if let Err((e, locked_file)) = removed {
+            if !is_in_use_error(&e) {
+                add_message(reporter, LogLevel::Error,
+                    &format!("Failed to delete directory '{:?}': {}", 
+                            target_dir, e));
+                continue;
+            }
+            let locked = locked_file.unwrap_or(target_dir);
+            add_message(reporter, LogLevel::Error, &format!(
+                    "{:?} is in use, so the old version can't be \
+                    removed. Close {} (and anything else using the \
+                    file) and try again.", locked, app_name));
+            return Err(InstallError::FileInUse(locked));
+        }
+        add_message(reporter, LogLevel::Debug,
+            &format!("Removed existing directory at {:?}", target_dir));
+        dirs_removed += 1;
+    }
+    Ok((shortcuts_removed, dirs_removed))
+}
+
+/// Moves the old app folder to `previous_dir`, replacing any copy an 
+/// interrupted run left there. A folder that can't be moved is deleted 
+/// instead, and then a failed update can't be rolled back.
+fn set_aside_install(reporter: &dyn ProgressReporter, app_dir: &Path, 
+        previous_dir: &Path) -> Result<(), (io::Error, Option<PathBuf>)> {
+    if previous_dir.exists() {
+        remove_dir_with_retries(previous_dir)?;
+    }
+    match fs::rename(app_dir, previous_dir) {
+        Ok(()) => {
+            add_message(reporter, LogLevel::Debug, &format!(
+                    "Moved the previous version to {:?}", previous_dir));
+            Ok(())
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Could not set the previous version aside ({}), so it \
+                    can't be restored if the update fails.", e));
+            remove_dir_with_retries(app_dir)
+        }
+    }
+}
+
+/// Windows errors for a file that another process has open or is deleting:
+/// access denied (a running exe), sharing and lock violations, and a 
+/// folder left non-empty by a pending delete.
+const FILE_IN_USE_ERRORS: [i32; 4] = [5, 32, 33, 145];
+const REMOVE_DIR_ATTEMPTS: u32 = 5;
+
+fn is_in_use_error(e: &io::Error) -> bool {
+    e.raw_os_error().is_some_and(|code| FILE_IN_USE_ERRORS.contains(&code))
+}
+
+/// Deletes `dir`, retrying for a couple of seconds while a file in it is in
+/// use (an exe still loading, a virus scan). If it stays locked, the error 
+/// comes back with the first file that can't be deleted, if one is found.
+fn remove_dir_with_retries(dir: &Path) 
+        -> Result<(), (io::Error, Option<PathBuf>)> {
+    let mut attempt = 1;
+    loop {
+        match fs::remove_dir_all(dir) {
+            Ok(()) => return Ok(()),
+            Err(e) if is_in_use_error(&e) && attempt < REMOVE_DIR_ATTEMPTS => {
+                attempt += 1;
+                thread::sleep(Duration::from_millis(500));
+            }
+            Err(e) if is_in_use_error(&e) => {
+                return Err((e, find_undeletable_file(dir)));
+            }
+            Err(e) => return Err((e, None)),
+        }
+    }
+}
+
+/// The first file below `dir` that can't be deleted. The ones that can be 
+/// are deleted on the way, as the folder was being removed anyway. Links 
+/// are skipped.
+fn find_undeletable_file(dir: &Path) -> Option<PathBuf> {
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        let locked = match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => find_undeletable_file(&path),
+            Ok(file_type) if file_type.is_file() => 
+                    fs::remove_file(&path).err().map(|_| path),
+            _ => None,
+        };
+        if locked.is_some() {
+            return locked;
+        }
+    }
+    None
+}
+
+/// Where Apps & features looks for programs, under HKEY_CURRENT_USER for 
+/// the current user's and HKEY_LOCAL_MACHINE for everyone's.
+const UNINSTALL_KEY: &str = 
+        r"Software\Microsoft\Windows\CurrentVersion\Uninstall";
+
+/// The hive holding this mode's Apps & features entries.
+fn uninstall_hive() -> RegKey {
+    RegKey::predef(if all_users() { 
+        HKEY_LOCAL_MACHINE 
+    } else { 
+        HKEY_CURRENT_USER 
+    })
+}
+
+/// Writes the app's Apps & features entry, whose Uninstall button runs the
+/// installer with --uninstall. Failures only cost the entry, so they are 
+/// warnings.
+fn register_uninstall_entry(reporter: &dyn ProgressReporter, app_name: &str,
+        app_dir: &Path, exe_path: &Path, display_name: &str, zip_path: &Path) {
+    // The local copy of the installer outlives the one on the share.
+    let installer = get_app_dir(reporter, "AppInstaller")
+        .map(|dir| dir.join("AppInstaller.exe"))
+        .filter(|installer| installer.exists())
+        .or_else(|| env::current_exe().ok());
+    let installer = match installer {
+        Some(installer) => installer,
+        None => {
+            add_message(reporter, LogLevel::Warn, 
+                    "Could not find the installer to uninstall with.");
+            return;
+        }
+    };
+    let mut uninstall_string = format!("\"{}\" --uninstall \"{}\"", 
+            installer.display(), app_name);
+    if let Some(root) = INSTALL_ROOT.lock().unwrap().as_ref() {
+        uninstall_string.push_str(&format!(" --install-root \"{}\"", 
+                root.display()));
+    }
+    if let Some(dir) = STAGING_DIR.lock().unwrap().as_ref() {
+        uninstall_string.push_str(&format!(" --staging-dir \"{}\"", 
+                dir.display()));
+    }
+    if all_users() {
+        uninstall_string.push_str(" --all-users");
+    }
+    let display_version = zip_version(zip_path).map(|v| v.to_string())
+        .or_else(|| version_utils::file_version(exe_path)
+                .map(|v| v.to_string()))
+        .unwrap_or_default();
+
+    let key_path = format!(r"{}\{}", UNINSTALL_KEY, app_name);
+    let written = uninstall_hive().create_subkey(&key_path)
+        .and_then(|(key, _)| {
+            key.set_value("DisplayName", &display_name)?;
+            key.set_value("DisplayVersion", &display_version)?;
+            key.set_value("DisplayIcon", &exe_path.as_os_str())?;
+            key.set_value("InstallLocation", &app_dir.as_os_str())?;
+            key.set_value("UninstallString", &uninstall_string)?;
+            key.set_value("NoModify", &1u32)?;
+            key.set_value("NoRepair", &1u32)
+        });
+    match written {
+        Ok(()) => add_message(reporter, LogLevel::Debug, &format!(
+                "Registered {} in Apps & features.", app_name)),
+        Err(e) => add_message(reporter, LogLevel::Warn, &format!(
+                "Failed to register {} in Apps & features: {}", app_name, e)),
+    }
+}
+
+/// Deletes the app's Apps & features entry, if it has one.
+fn remove_uninstall_entry(reporter: &dyn ProgressReporter, app_name: &str) {
+    let key_path = format!(r"{}\{}", UNINSTALL_KEY, app_name);
+    let uninstall_key = uninstall_hive();
+    if uninstall_key.open_subkey(&key_path).is_err() {
+        return;
+    }
+    if dry_run() {
+        add_message(reporter, LogLevel::Info, &format!(
+                "{}Would remove {} from Apps & features", DRY_RUN_PREFIX, 
+                app_name));
+    } else if let Err(e) = uninstall_key.delete_subkey_all(&key_path) {
+        add_message(reporter, LogLevel::Warn, &format!(
+                "Failed to remove {} from Apps & features: {}", app_name, e));
+    } else {
+        add_message(reporter, LogLevel::Debug, &format!(
+                "Removed {} from Apps & features.", app_name));
+    }
+}
+
+/// The installable apps: one per subdirectory of the source directory, 
+/// sorted by name. The installer's own directory is left out.
+pub fn list_available_apps() -> io::Result<Vec<String>> {
+    let remote_dir = REMOTE_DIR.lock().unwrap().clone();
+    let mut apps = Vec::new();
+    for entry in fs::read_dir(&remote_dir)? {
+        let entry = entry?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str()
+                .filter(|name| *name != "AppInstaller") {
+            apps.push(name.to_string());
+        }
+    }
+    apps.sort_by_key(|name| name.to_lowercase());
+    Ok(apps)
+}
+
+/// Fails fast, suggesting the nearest app name, when the source has no 
+/// folder for `app_name` (or its --channel). A URL source can't be checked 
+/// without a download, so it passes, as does any app given a --zip file.
+fn check_app_source(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Result<(), InstallError> {
+    if remote_url().is_some() || zip_given(app_name) || 
+            app_source_dir(app_name).is_dir() {
+        return Ok(());
+    }
+    let mut message = format!("{:?} was not found.", app_source_dir(app_name));
+    if let Some(name) = list_available_apps().ok()
+            .and_then(|apps| closest_app_name(app_name, &apps)) {
+        message.push_str(&format!(" Did you mean '{}'?", name));
+    }
+    add_message(reporter, LogLevel::Error, &message);
+    Err(InstallError::AppNotFound(app_name.to_string()))
+}
+
+/// The app whose name is fewest edits from `app_name`, ignoring case, if it
+/// is close enough to be a likely typo.
+fn closest_app_name(app_name: &str, apps: &[String]) -> Option<String> {
+    let wanted = app_name.to_lowercase();
+    let max_distance = (wanted.chars().count() / 3).max(2);
+    apps.iter()
+        .map(|name| (edit_distance(&wanted, &name.to_lowercase()), name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name.clone())
+}
+
+/// Levenshtein distance: the single-character insertions, deletions and 
+/// substitutions that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1)
+                    .min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The source as a base URL (without a trailing slash) when REMOTE_DIR is an
+/// http:// or https:// address.
+fn remote_url() -> Option<String> {
+    let remote_dir = REMOTE_DIR.lock().unwrap().clone();
+    let source = remote_dir.to_str()?;
+    if http_utils::is_url(source) {
+        Some(source.trim_end_matches('/').to_string())
+    } else {
+        None
+    }
+}
+
+/// Downloads `<source>/<app_name>/latest.zip` (with --channel,
+/// `<source>/<app_name>/<channel>/latest.zip`) into the Utils folder.
+fn download_latest_zip(reporter: &dyn ProgressReporter,
+        base_url: &str, app_name: &str) -> Result<PathBuf, InstallError> {
+    let url = format!("{}/{}/latest.zip", base_url, app_source_path(app_name));
+    let local_appdata = match get_local_appdata(reporter) {
+        Some(path) => path,
+        None => {
+            add_message(reporter, LogLevel::Error, 
+                    "Could not find LOCALAPPDATA directory.");
+            return Err(InstallError::CopyFailed);
+        }
+    };
+    let dest_path = local_appdata.join(format!("{}-latest.zip", app_name));
+    add_message(reporter, LogLevel::Debug, &format!("Downloading {}", url));
+
+    match http_utils::download_with_progress(&url, &dest_path, 
+            |progress| reporter.progress(progress, &format!(
+                    "Downloading {} ({}%)", url, progress)), is_cancelled) {
+        Ok(_) => {
+            add_message(reporter, LogLevel::Debug, &format!(
+                    "Downloaded {} to {:?}", url, dest_path));
+            Ok(dest_path)
+        }
+        Err(_) if is_cancelled() => {
+            let _ = fs::remove_file(&dest_path);
+            Err(InstallError::Cancelled)
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, 
+                    &format!("Failed to download {}: {}", url, e));
+            let _ = fs::remove_file(&dest_path);
+            Err(InstallError::CopyFailed)
+        }
+    }
+}
+
+/// Copies the zip to install into the Utils folder (or finds where it can be
+/// read in place). Also returns how much of the progress bar, as a 
+/// percentage, the copy used, which is where the extraction starts, and 
+/// whether the zip is a copy the caller should delete; one read in place is
+/// the source's only copy of the build.
+fn copy_latest_zip(reporter: &dyn ProgressReporter, 
+        app_name: &str) -> Result<(PathBuf, u32, bool), InstallError> {
+    let pinned_version = pinned_version(app_name);
+    if let Some(base_url) = remote_url() {
+        if let Some(version) = pinned_version {
+            add_message(reporter, LogLevel::Error, &format!(
+                    "--version {} needs a folder source; a URL source only \
+                    serves latest.zip.", version));
+            return Err(InstallError::NoZipFound);
+        }
+        // The zip's contents aren't known until it has been downloaded.
+        return download_latest_zip(reporter, &base_url, app_name)
+            .map(|dest_path| (dest_path, 0, true));
+    }
+    let source_dir_path = app_source_dir(app_name);
+    add_message(reporter, LogLevel::Debug,
+        &format!("Searching for zip files in {:?}", source_dir_path));
+
+    let newest_file = match find_zip_to_install(&source_dir_path, app_name) {
+        Ok(newest_file) => newest_file,
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, &format!(
+                    "Source directory not found or unreadable: {:?}: {}",
+                    source_dir_path, e));
+            return Err(InstallError::NoZipFound);
+        }
+    };
+
+    if let Some((newest_file_path, _)) = newest_file.clone() {
+        add_message(reporter, LogLevel::Debug,
+            &format!("Found latest zip file: {:?}", newest_file_path));
+        if let Some(subfolder) = newest_file_path.parent()
+                .filter(|parent| *parent != source_dir_path)
+                .and_then(Path::file_name) {
+            add_message(reporter, LogLevel::Info, &format!(
+                    "Using the zip in the {:?} subfolder of {:?}", 
+                    subfolder, source_dir_path));
+        }
+        if let Some(version) = pinned_version {
+            add_message(reporter, LogLevel::Info, 
+                    &format!("Pinned version: {}", version));
+        } else if let Some(version) = zip_version(&newest_file_path) {
+            add_message(reporter, LogLevel::Info, 
+                    &format!("Latest version: {}", version));
+        }
+        report_build_info(reporter, 
+                newest_file_path.parent().unwrap_or(&source_dir_path));
+        if let Some(local_appdata) = get_local_appdata(reporter) {
+            let file_name = match newest_file_path.file_name() {
+                Some(name) => name,
+                None => {
+                    add_message(reporter, LogLevel::Error,
+                            "Could not get file name from path."); 
+                    return Err(InstallError::CopyFailed);
+                }
+            };
+            let dest_path = local_appdata.join(file_name);
+            // A source that is the Utils folder itself, perhaps reached 
+            // through a share, must not be copied onto itself.
+            if comparable_path(&newest_file_path) == 
+                    comparable_path(&dest_path) {
+                add_message(reporter, LogLevel::Debug, 
+                        "The zip is already in the Utils folder.");
+                if !verify_checksum(reporter, &newest_file_path, 
+                        &newest_file_path) {
+                    return Err(InstallError::ChecksumMismatch);
+                }
+                return Ok((newest_file_path, 0, false));
+            }
+            if dry_run() {
+                add_message(reporter, LogLevel::Info, &format!(
+                        "{}Would copy {:?} to {:?}", DRY_RUN_PREFIX, 
+                        newest_file_path, dest_path));
+                if !verify_checksum(reporter, &newest_file_path, 
+                        &newest_file_path) {
+                    return Err(InstallError::ChecksumMismatch);
+                }
+                return Ok((newest_file_path, 0, false));
+            }
+
+            // Without the zip's sizes the copy and the extraction each fill
+            // the bar.
+            let copy_share = copy_progress_share(&newest_file_path);
+            let copy_reporter = ProgressSpan { reporter, start: 0, 
+                    end: copy_share.unwrap_or(100) };
+            //ui::show_progress();
+            let result = copy_with_retries(&copy_reporter, 
+                        &newest_file_path, &dest_path);
+	    //ui::hide_progress();
+
+            match result {
+                Ok(_) => {
+                    add_message(reporter, LogLevel::Debug, &format!(
+                            "Copied latest version {:?} to {:?}", 
+                            file_name, dest_path)); 
+                    if !verify_checksum(reporter, &newest_file_path, 
+                            &dest_path) {
+                        let _ = fs::remove_file(&dest_path);
+                        return Err(InstallError::ChecksumMismatch);
+                    }
+                    return Ok((dest_path, copy_share.unwrap_or(0), true));
+                }
+                Err(_) if is_cancelled() => {
+                    let _ = fs::remove_file(&dest_path);
+                    return Err(InstallError::Cancelled);
+                }
+                Err(e) => {
+                    add_message(reporter, LogLevel::Error, 
+                        &format!("Error copying file: {}", e));
+                    let _ = fs::remove_file(&dest_path);
+                    return Err(InstallError::CopyFailed);
+                },
+            }
+        } else {
+            add_message(reporter, LogLevel::Error, 
+                    "Could not find LOCALAPPDATA directory.");
+            return Err(InstallError::CopyFailed);
+        }
+    } else if let Some(version) = pinned_version {
+        add_message(reporter, LogLevel::Error, &format!(
+                "No zip for version {} found in {:?}", version, 
+                source_dir_path));
+    } else {
+        add_message(reporter, LogLevel::Error, 
+                &format!("No .zip files found in {:?}", source_dir_path)); 
+    }
+    Err(InstallError::NoZipFound)
+}
+
+/// --zip, unless `app_name` is the installer itself.
+fn local_zip(app_name: &str) -> Option<PathBuf> {
+    if app_name == "AppInstaller" {
+        return None;
+    }
+    LOCAL_ZIP.lock().unwrap().clone()
+}
+
+/// --stdin, unless `app_name` is the installer itself.
+fn zip_from_stdin(app_name: &str) -> bool {
+    app_name != "AppInstaller" && *ZIP_FROM_STDIN.lock().unwrap()
+}
+
+/// Whether the zip was given with --zip or --stdin, so the source isn't 
+/// looked at.
+fn zip_given(app_name: &str) -> bool {
+    local_zip(app_name).is_some() || zip_from_stdin(app_name)
+}
+
+/// Saves the zip piped to stdin as `<app_name>-stdin.zip` in the Utils 
+/// folder, where unzip_file can seek in it, and returns its path. Even a 
+/// dry run has to save it to look inside.
+fn read_zip_from_stdin(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Result<PathBuf, InstallError> {
+    let dest_path = match get_local_appdata(reporter) {
+        Some(dir) => dir.join(format!("{}-stdin.zip", app_name)),
+        None => return Err(InstallError::CopyFailed),
+    };
+    reporter.busy("Reading the zip from stdin");
+    let copied = File::create(&dest_path).and_then(|mut output| 
+            copy_until_cancelled(&mut io::stdin().lock(), &mut output));
+    match copied {
+        Ok(0) => {
+            add_message(reporter, LogLevel::Error, 
+                    "Nothing was piped to stdin.");
+            let _ = fs::remove_file(&dest_path);
+            Err(InstallError::NoZipFound)
+        }
+        Ok(size) => {
+            add_message(reporter, LogLevel::Info, &format!(
+                    "Read a {} zip from stdin.", format_size(size)));
+            Ok(dest_path)
+        }
+        Err(_) if is_cancelled() => {
+            let _ = fs::remove_file(&dest_path);
+            Err(InstallError::Cancelled)
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, 
+                    &format!("Failed to read the zip from stdin: {}", e));
+            let _ = fs::remove_file(&dest_path);
+            Err(InstallError::CopyFailed)
+        }
+    }
+}
+
+/// Copies `from` to `to` a buffer at a time, stopping with an Interrupted 
+/// error if the install is cancelled. Returns the bytes copied.
+fn copy_until_cancelled(from: &mut impl Read, to: &mut impl Write) 
+        -> io::Result<u64> {
+    let mut buffer = vec![0; *COPY_BUFFER_SIZE.lock().unwrap()];
+    let mut copied = 0;
+    loop {
+        if is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, 
+                    "Copy cancelled"));
+        }
+        let bytes_read = from.read(&mut buffer)?;
+        if bytes_read == 0 {
+            return Ok(copied);
+        }
+        to.write_all(&buffer[..bytes_read])?;
+        copied += bytes_read as u64;
+    }
+}
+
+/// Checks that a --zip file exists and, with --verify-checksum, matches the
+/// `.sha256` file next to it. It takes the place of copy_latest_zip.
+fn check_local_zip(reporter: &dyn ProgressReporter, zip_path: &Path) 
+        -> Result<(), InstallError> {
+    if !zip_path.is_file() {
+        add_message(reporter, LogLevel::Error, 
+                &format!("Zip file not found: {:?}", zip_path));
+        return Err(InstallError::NoZipFound);
+    }
+    add_message(reporter, LogLevel::Info, 
+            &format!("Installing from {:?}", zip_path));
+    if !verify_checksum(reporter, zip_path, zip_path) {
+        return Err(InstallError::ChecksumMismatch);
+    }
+    Ok(())
+}
+
+/// --channel, unless `app_name` is the installer itself.
+fn channel(app_name: &str) -> Option<String> {
+    if app_name == "AppInstaller" {
+        return None;
+    }
+    CHANNEL.lock().unwrap().clone()
+}
+
+/// The folder holding `app_name`'s zips.
+fn app_source_dir(app_name: &str) -> PathBuf {
+    let app_dir = REMOTE_DIR.lock().unwrap().clone().join(app_name);
+    match channel(app_name) {
+        Some(channel) => app_dir.join(channel),
+        None => app_dir,
+    }
+}
+
+/// `app_name`, followed by `/<channel>` with --channel, for URL sources.
+fn app_source_path(app_name: &str) -> String {
+    match channel(app_name) {
+        Some(channel) => format!("{}/{}", app_name, channel),
+        None => app_name.to_string(),
+    }
+}
+
+/// --version, unless `app_name` is the installer itself.
+fn pinned_version(app_name: &str) -> Option<version_utils::Version> {
+    if app_name == "AppInstaller" {
+        return None;
+    }
+    PINNED_VERSION.lock().unwrap().clone()
+}
+
+/// The zip to install `app_name` from: the newest one, or with --version the
+/// newest of those whose name carries exactly that version.
+fn find_zip_to_install(dir: &Path, app_name: &str) 
+        -> io::Result<Option<(PathBuf, SystemTime)>> {
+    let version = match pinned_version(app_name) {
+        Some(version) => version,
+        None => return find_newest_zip(dir),
     };
-    if result == S_OK {
-        let len = unsafe {
-            (0..).take_while(|&i| *path_ptr.offset(i) != 0).count()
-        };
-        let path_slice = unsafe {
-            std::slice::from_raw_parts(path_ptr, len)
-        };
-        let os_string: OsString = OsStringExt::from_wide(path_slice);
-        let mut path = PathBuf::from(os_string);
-        path.push("Utils");
-        if !path.exists() {
-            if let Err(e) = fs::create_dir_all(&path) {
-                add_message(&listview, "ERROR",
-                    &format!("Failed to create directory {:?}: {}", path, e));
-                return None;
-            }
+    Ok(list_zips(dir)?.into_iter()
+        .filter(|(path, _)| zip_version(path).as_ref() == Some(&version))
+        .max_by_key(|(_, modified)| *modified))
+}
+
+fn find_newest_zip(dir: &Path) -> io::Result<Option<(PathBuf, SystemTime)>> {
+    let zips = list_zips(dir)?;
+    if *NEWEST_BY.lock().unwrap() == NewestBy::Version {
+        let newest_by_version = zips.iter()
+            .filter_map(|(path, modified)| 
+                    zip_version(path).map(|version| (version, modified, path)))
+            .max_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(b.1)));
+        if let Some((_, modified, path)) = newest_by_version {
+            return Ok(Some((path.clone(), *modified)));
+        }
+    }
+    Ok(zips.into_iter().max_by_key(|(_, modified)| *modified))
+}
+
+const STABLE_SUBFOLDER: &str = "stable";
+
+/// The archives directly in `dir`, or if there are none, those one folder
+/// down, for sources that keep each build in its own subfolder. A `stable`
+/// subfolder is taken on its own, so channels kept side by side (`stable`, 
+/// `beta`) aren't mixed when --channel isn't given.
+fn list_zips(dir: &Path) -> io::Result<Vec<(PathBuf, SystemTime)>> {
+    let zips = list_zips_in(dir)?;
+    if !zips.is_empty() {
+        return Ok(zips);
+    }
+    let stable_dir = dir.join(STABLE_SUBFOLDER);
+    if stable_dir.is_dir() {
+        return Ok(list_zips_in(&stable_dir).unwrap_or_default());
+    }
+    let mut nested = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            nested.extend(list_zips_in(&path).unwrap_or_default());
+        }
+    }
+    Ok(nested)
+}
+
+fn list_zips_in(dir: &Path) -> io::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut zips = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if !path.is_file() || !is_archive(&path) {
+            continue;
+        }
+        if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+            zips.push((path, modified));
+        }
+    }
+    Ok(zips)
+}
+
+/// Zips, and with the sevenz feature 7-Zip archives too.
+fn is_archive(path: &Path) -> bool {
+    let extension = path.extension().and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase());
+    extension.as_deref() == Some("zip") || 
+        (cfg!(feature = "sevenz") && extension.as_deref() == Some("7z"))
+}
+
+fn zip_version(zip_path: &Path) -> Option<version_utils::Version> {
+    zip_path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(version_utils::parse_version_from_filename)
+}
+
+/// An app's newest version in the source and the version installed here, 
+/// for --list.
+pub struct AppVersions {
+    pub name: String,
+    pub latest: Option<String>,
+    pub installed: Option<String>,
+}
+
+impl AppVersions {
+    /// How the installed version compares with the latest.
+    pub fn status(&self) -> &'static str {
+        match (&self.latest, &self.installed) {
+            (_, None) => "not installed",
+            (None, Some(_)) => "not in the source",
+            (Some(latest), Some(installed)) if latest == installed => 
+                    "up to date",
+            (Some(_), Some(_)) => "update available",
+        }
+    }
+}
+
+/// Every app in the source, plus any installed here that the source no 
+/// longer has, with their versions. Nothing is created or changed.
+pub fn list_app_versions() -> io::Result<Vec<AppVersions>> {
+    let mut apps: Vec<AppVersions> = list_available_apps()?.into_iter()
+        .map(app_versions)
+        .collect();
+    let installed_only: Vec<AppVersions> = installed_app_names().into_iter()
+        .filter(|name| !apps.iter().any(|app| 
+                app.name.eq_ignore_ascii_case(name)))
+        .map(|name| AppVersions { latest: None, 
+                installed: installed_version(&name), name })
+        .collect();
+    apps.extend(installed_only);
+    apps.sort_by_key(|app| app.name.to_lowercase());
+    Ok(apps)
+}
+
+/// The versions of one app: the newest zip in its source folder (with 
+/// --channel, that channel's) and its installed .version file.
+fn app_versions(name: String) -> AppVersions {
+    AppVersions {
+        latest: find_newest_zip(&app_source_dir(&name)).ok().flatten()
+            .and_then(|(path, _)| zip_version(&path))
+            .map(|version| version.to_string()),
+        installed: installed_version(&name),
+        name,
+    }
+}
+
+/// --check-update's exit code when the installed version differs from the
+/// latest, usually by being older.
+pub const EXIT_UPDATE_AVAILABLE: i32 = 2;
+/// --check-update's exit code when the app isn't installed here.
+pub const EXIT_NOT_INSTALLED: i32 = 3;
+
+/// Compares an app's installed version with the latest in the source and 
+/// prints both, changing nothing. Returns the exit code: 0 when up to date,
+/// EXIT_UPDATE_AVAILABLE, EXIT_NOT_INSTALLED, or 1 when the latest version
+/// can't be found.
+pub fn check_for_update(app_name: &str) -> i32 {
+    if remote_url().is_some() {
+        eprintln!("--check-update needs a folder source; a URL source can't \
+                be inspected without downloading latest.zip.");
+        return 1;
+    }
+    let app = app_versions(app_name.to_string());
+    println!("{}: installed {}, latest {} ({})", app.name, 
+            app.installed.as_deref().unwrap_or("-"), 
+            app.latest.as_deref().unwrap_or("-"), app.status());
+    match (&app.latest, &app.installed) {
+        (None, _) => {
+            eprintln!("No versioned zip found in {:?}", 
+                    app_source_dir(app_name));
+            1
+        }
+        (Some(_), None) => EXIT_NOT_INSTALLED,
+        (Some(latest), Some(installed)) if latest == installed => 0,
+        (Some(_), Some(_)) => EXIT_UPDATE_AVAILABLE,
+    }
+}
+
+/// The install root without get_app_dir's side effect of creating it.
+fn existing_install_root() -> Option<PathBuf> {
+    match INSTALL_ROOT.lock().unwrap().clone() {
+        Some(root) => Some(root),
+        None if all_users() => all_users_root(),
+        None => staging_dir(),
+    }
+}
+
+/// The version recorded by the last install of `app_name`, if any.
+fn installed_version(app_name: &str) -> Option<String> {
+    let app_dir = existing_install_root()?.join(app_name);
+    fs::read_to_string(app_dir.join(VERSION_FILE_NAME)).ok()
+        .map(|version| version.trim().to_string())
+}
+
+/// The folders in the install root that hold a version file.
+fn installed_app_names() -> Vec<String> {
+    let entries = match existing_install_root().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return Vec::new(),
+    };
+    entries.flatten()
+        .filter(|entry| entry.path().join(VERSION_FILE_NAME).is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name != "AppInstaller")
+        .collect()
+}
+
+/// Prints --list's table of apps and versions. Returns false, having 
+/// printed why, if the source can't be read.
+pub fn print_app_list() -> bool {
+    let apps = match list_app_versions() {
+        Ok(apps) => apps,
+        Err(e) => {
+            eprintln!("Could not list applications in {:?}: {}", 
+                    REMOTE_DIR.lock().unwrap(), e);
+            return false;
+        }
+    };
+    let name_width = apps.iter().map(|app| app.name.len())
+        .chain(Some("App".len())).max().unwrap_or_default();
+    println!("{:<name_width$}  {:<12}  {:<12}  Status", "App", "Latest", 
+            "Installed");
+    for app in &apps {
+        println!("{:<name_width$}  {:<12}  {:<12}  {}", app.name, 
+                app.latest.as_deref().unwrap_or("-"), 
+                app.installed.as_deref().unwrap_or("-"), app.status());
+    }
+    true
+}
+
+/// Logs a row per app for --list in the window.
+pub fn report_app_list(reporter: &dyn ProgressReporter) {
+    let apps = match list_app_versions() {
+        Ok(apps) => apps,
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, &format!(
+                    "Could not list applications in {:?}: {}", 
+                    REMOTE_DIR.lock().unwrap(), e));
+            return;
         }
-        Some(path)
+    };
+    for app in &apps {
+        add_message(reporter, LogLevel::Info, &format!(
+                "{}: latest {}, installed {} ({})", app.name, 
+                app.latest.as_deref().unwrap_or("-"), 
+                app.installed.as_deref().unwrap_or("-"), app.status()));
+    }
+}
+
+/// Verifies a zip without extracting it. `target` is either a path to a zip
+/// file or an app name, in which case the newest zip in the app's source
+/// directory is checked. Problems are printed and false is returned.
+pub fn check_zip(target: &str) -> bool {
+    let zip_path = if Path::new(target).is_file() {
+        PathBuf::from(target)
     } else {
-        None
+        let source_dir_path = app_source_dir(target);
+        match find_newest_zip(&source_dir_path) {
+            Ok(Some((path, _))) => path,
+            Ok(None) => {
+                eprintln!("No .zip files found in {:?}", source_dir_path);
+                return false;
+            }
+            Err(e) => {
+                eprintln!("Source directory not found or unreadable: {:?}: {}",
+                        source_dir_path, e);
+                return false;
+            }
+        }
+    };
+    println!("Checking {:?}", zip_path);
+
+    let mut reader = match File::open(&zip_path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            eprintln!("Unable to open zip file: {}", e);
+            return false;
+        }
+    };
+
+    let entries = match zip_utils::read_central_directory(&mut reader) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to parse zip file: {}", e);
+            return false;
+        }
+    };
+    if entries.is_empty() {
+        eprintln!("No entries found in the central directory.");
+        return false;
+    }
+
+    let mut failures = 0;
+    for entry in &entries {
+        if let Err(e) = zip_utils::verify_file(entry, &mut reader) {
+            eprintln!("{}: {}", entry.file_name, e);
+            failures += 1;
+        }
     }
+    println!("{} entries checked, {} failed.", entries.len(), failures);
+    failures == 0
 }
 
-pub fn run_installation(listview: &nwg::ListView, bar: &nwg::ProgressBar, 
-        app_name: &str) {
-    update_installer(&listview, bar);
+const MANIFEST_FILE_NAME: &str = "install.json";
 
-    add_message(&listview, "INFO", &format!("Starting installation for {}",
-            app_name));
+/// Per-app install settings kept next to the zips as `install.json`. Any 
+/// field left out keeps the default behaviour.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct AppManifest {
+    /// Shortcut name, in place of the exe's product name.
+    display_name: Option<String>,
+    /// The exe to link and run, relative to the install folder, in place of 
+    /// the one find_executable picks.
+    main_exe: Option<String>,
+    /// Arguments stored in the shortcut unless --shortcut-args is given.
+    launch_args: Option<String>,
+    /// An .ico for the shortcuts, relative to the install folder, in place 
+    /// of `<exe name>.ico` next to the exe or else the exe's own icon.
+    icon: Option<String>,
+    /// Also put a shortcut on the Desktop, like --desktop-shortcut.
+    create_desktop_shortcut: bool,
+    /// What a reinstall keeps from the old app folder: "name/" is a folder, 
+    /// anything else a file, with "*" and "?" wildcards. Defaults to 
+    /// DEFAULT_KEEP_USER_DATA.
+    keep_user_data: Option<Vec<String>>,
+    /// Kept paths whose old copy replaces a file of the same name in the new
+    /// version. Otherwise the freshly extracted file wins.
+    user_owned: Vec<String>,
+    /// More Start Menu shortcuts, e.g. to helper scripts, made with the main
+    /// one and removed with it.
+    shortcuts: Vec<ExtraShortcut>,
+    /// The app needs Windows to restart before it works, e.g. because it 
+    /// installs a driver.
+    reboot_required: bool,
+}
 
-    let process_name = format!("{}.exe", app_name);
-    if check_if_running(&process_name) {
-        add_message(&listview, "ERROR",
-            &format!( "'{}' is running. Please close it and try again.",
-                app_name
-            )
-        );
-        return;
+/// A manifest `shortcuts` entry.
+#[derive(Deserialize)]
+struct ExtraShortcut {
+    /// The shortcut's name, without `.lnk`.
+    name: String,
+    /// The file it opens, relative to the install folder.
+    target: String,
+    #[serde(default)]
+    args: Option<String>,
+    /// An .ico relative to the install folder. Without one the shortcut 
+    /// shows the target's icon.
+    #[serde(default)]
+    icon: Option<String>,
+}
+
+/// Reads the app's `install.json` from the source directory: with 
+/// --channel the channel's folder is tried first, so a channel can ship its
+/// own, then the app's folder. A missing or unreadable manifest means the 
+/// defaults.
+fn load_manifest(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> AppManifest {
+    if remote_url().is_some() {
+        return AppManifest::default();
+    }
+    let app_dir = REMOTE_DIR.lock().unwrap().clone().join(app_name);
+    let mut manifest_paths = vec![app_source_dir(app_name)
+            .join(MANIFEST_FILE_NAME)];
+    if channel(app_name).is_some() {
+        manifest_paths.push(app_dir.join(MANIFEST_FILE_NAME));
+    }
+    let found = manifest_paths.iter().find_map(|manifest_path| 
+            fs::read_to_string(manifest_path).ok()
+                .map(|text| (manifest_path, text)));
+    let (manifest_path, text) = match found {
+        Some(found) => found,
+        None => {
+            add_message(reporter, LogLevel::Debug, &format!(
+                    "No manifest found at {:?}", manifest_paths));
+            return AppManifest::default();
+        }
+    };
+    match serde_json::from_str::<AppManifest>(&text) {
+        Ok(manifest) => {
+            add_message(reporter, LogLevel::Debug, 
+                    &format!("Loaded manifest {:?}", manifest_path));
+            manifest
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Failed to parse {:?}: {}. Using the defaults.", 
+                    manifest_path, e));
+            AppManifest::default()
+        }
+    }
+}
+
+/// The manifest's main_exe if it was extracted, otherwise the exe 
+/// find_executable picks.
+fn main_executable(reporter: &dyn ProgressReporter, app_dir: &Path, 
+        app_name: &str, manifest: &AppManifest) -> Option<PathBuf> {
+    if let Some(main_exe) = &manifest.main_exe {
+        // The manifest comes from the share, so like a zip entry it may not
+        // point outside the app folder.
+        match zip_utils::join_entry_path(app_dir, 
+                &main_exe.replace('/', "\\")) {
+            Ok(exe_path) if exe_path.is_file() => return Some(exe_path),
+            Ok(_) => add_message(reporter, LogLevel::Warn, &format!(
+                    "Manifest main_exe {:?} not found in {:?}. Looking for \
+                    the executable instead.", main_exe, app_dir)),
+            Err(e) => add_message(reporter, LogLevel::Warn, &format!(
+                    "Manifest main_exe {:?} can't be used: {}. Looking for \
+                    the executable instead.", main_exe, e)),
+        }
     }
+    find_executable(app_dir, app_name)
+}
 
-    uninstall_application(&listview, app_name);
+/// Kept across a reinstall when the manifest doesn't list keep_user_data.
+const DEFAULT_KEEP_USER_DATA: [&str; 3] = ["config/", "data/", "*.ini"];
 
-    if let Some(copied_zip_path) = copy_latest_zip(&listview, &bar, app_name) {
-        unzip_file(&listview, &copied_zip_path, app_name);
+/// Where user data waits while the app folder is replaced. It sits next to 
+/// the app folder so moving files there is a rename.
+fn user_data_stash(reporter: &dyn ProgressReporter, app_name: &str) 
+        -> Option<PathBuf> {
+    get_app_dir(reporter, app_name)
+        .map(|app_dir| app_dir.with_file_name(
+                format!("{}.userdata", app_name)))
+}
 
-        if let Some(local_appdata) = get_local_appdata(&listview) {
-            let app_dir = local_appdata.join(app_name);
-            if let Some(exe_path) = find_executable(&app_dir) {
-                add_message(&listview, "DEBUG", 
-                        &format!("Found executable at {:?}", exe_path));
-                if let Some(exe_str) = exe_path.to_str() {
-                    create_shortcut(&listview, exe_str, app_name);
-                    *EXE_PATH_TO_RUN.lock().unwrap() = Some(exe_path.clone());
-                } else {
-                    add_message(&listview, "ERROR",
-                        "Executable path contains invalid characters.");
-                }
+/// Moves whatever in `dir` matches `patterns` into the same place under 
+/// `stash_dir`. A matching folder moves whole, so folders nested in it need 
+/// no patterns of their own. `rel` is `dir` relative to the app folder.
+fn stash_user_data(reporter: &dyn ProgressReporter, dir: &Path, rel: &Path, 
+        stash_dir: &Path, patterns: &[String]) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let entry_rel = rel.join(entry.file_name());
+        let is_dir = path.is_dir();
+        if !patterns.iter().any(|pattern| 
+                user_data_matches(pattern, &entry_rel, is_dir)) {
+            if is_dir {
+                stash_user_data(reporter, &path, &entry_rel, stash_dir, 
+                        patterns);
+            }
+            continue;
+        }
+        if dry_run() {
+            add_message(reporter, LogLevel::Info, &format!(
+                    "{}Would keep user data {:?}", DRY_RUN_PREFIX, entry_rel));
+            continue;
+        }
+        let stash_path = stash_dir.join(&entry_rel);
+        if stash_path.exists() {
+            // Left by an interrupted install; that copy is the older one, 
+            // and the current one would be deleted with the app folder.
+            let removed = if stash_path.is_dir() {
+                fs::remove_dir_all(&stash_path)
             } else {
-                add_message(&listview, "ERROR",
-                    &format!("Could not find executable for {}", app_name),
-                );
+                fs::remove_file(&stash_path)
+            };
+            match removed {
+                Ok(()) => add_message(reporter, LogLevel::Debug, &format!(
+                        "Replacing user data {:?} kept from an earlier run", 
+                        entry_rel)),
+                Err(e) => {
+                    add_message(reporter, LogLevel::Warn, &format!(
+                            "Failed to replace user data {:?} kept from an \
+                            earlier run: {}", entry_rel, e));
+                    continue;
+                }
             }
         }
+        let moved = stash_path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(&path, &stash_path));
+        match moved {
+            Ok(()) => add_message(reporter, LogLevel::Debug, 
+                    &format!("Keeping user data {:?}", entry_rel)),
+            Err(e) => add_message(reporter, LogLevel::Warn, &format!(
+                    "Failed to keep user data {:?}: {}", entry_rel, e)),
+        }
+    }
+}
 
-        if let Err(e) = fs::remove_file(&copied_zip_path) {
-            add_message(&listview, "ERROR",
-                &format!("Failed to delete temporary zip file: {}", e),
-            );
+/// Moves the kept user data back into the freshly extracted app folder. 
+/// Where the new version has a file of the same name, the new file wins 
+/// unless the manifest lists it as user_owned.
+fn restore_user_data(reporter: &dyn ProgressReporter, app_name: &str, 
+        manifest: &AppManifest) {
+    if dry_run() {
+        return;
+    }
+    let stash_dir = match user_data_stash(reporter, app_name) {
+        Some(stash_dir) if stash_dir.is_dir() => stash_dir,
+        _ => return,
+    };
+    let app_dir = match get_app_dir(reporter, app_name) {
+        Some(app_dir) => app_dir,
+        None => return,
+    };
+    let mut failed = false;
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(rel) = dirs.pop() {
+        let entries = match fs::read_dir(stash_dir.join(&rel)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let entry_rel = rel.join(entry.file_name());
+            if entry.path().is_dir() {
+                dirs.push(entry_rel);
+                continue;
+            }
+            let dest = app_dir.join(&entry_rel);
+            if dest.exists() {
+                let user_owned = manifest.user_owned.iter().any(|pattern| 
+                        entry_rel.ancestors().any(|path| 
+                            user_data_matches(pattern, path, 
+                                    path != entry_rel.as_path())));
+                if !user_owned {
+                    add_message(reporter, LogLevel::Debug, &format!(
+                            "Keeping the new {:?} over the old copy", 
+                            entry_rel));
+                    continue;
+                }
+                let _ = fs::remove_file(&dest);
+            }
+            let restored = dest.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::rename(entry.path(), &dest));
+            if let Err(e) = restored {
+                add_message(reporter, LogLevel::Warn, &format!(
+                        "Failed to restore user data {:?}: {}", entry_rel, e));
+                failed = true;
+            }
         }
+    }
+    if failed {
+        add_message(reporter, LogLevel::Warn, &format!(
+                "Some user data was left in {:?}", stash_dir));
+    } else if let Err(e) = fs::remove_dir_all(&stash_dir) {
+        add_message(reporter, LogLevel::Debug, &format!(
+                "Failed to delete {:?}: {}", stash_dir, e));
     } else {
-        add_message(&listview, "ERROR", 
-                &format!("Installation failed for {}.", app_name));
+        add_message(reporter, LogLevel::Info, "Restored user data.");
     }
-    add_message(&listview, "INFO", "Installation process finished.");
 }
 
-pub fn add_message(listview: &nwg::ListView, message_type: &str, message: &str) {
-    if message_type == "DEBUG" && !*DEBUG.lock().unwrap() {
-        return;
+/// Matches a keep_user_data pattern against a path relative to the app 
+/// folder, ignoring case. A trailing "/" makes the pattern match folders 
+/// only. A pattern with no other "/" matches the name at any depth, 
+/// otherwise the whole path. "*" and "?" are wildcards.
+fn user_data_matches(pattern: &str, rel: &Path, is_dir: bool) -> bool {
+    let dir_only = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/').replace('\\', "/")
+        .to_lowercase();
+    if (dir_only && !is_dir) || pattern.is_empty() {
+        return false;
     }
-    let time_str = Local::now().format("%H:%M:%S").to_string();
-    listview.insert_item(message_type);
-    let new_index = (listview.len() - 1) as i32;
-    listview.insert_item(nwg::InsertListViewItem { 
-        index: Some(new_index),
-        column_index: 1,
-        text: Some(time_str.into()),
-        image: None
-    });
-    listview.insert_item(nwg::InsertListViewItem { 
-        index: Some(new_index),
-        column_index: 2,
-        text: Some(message.into()),
-        image: None
-    });
+    let text = if pattern.contains('/') {
+        rel.to_string_lossy().replace('\\', "/")
+    } else {
+        match rel.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return false,
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    wildcard_match(&pattern, &text)
 }
 
-fn update_installer(listview: &nwg::ListView, bar: &nwg::ProgressBar) {
-    add_message(&listview, "INFO", "Checking for installer updates...");
-    if let Some(local_appdata) = get_local_appdata(&listview) {
-        let local_installer_path = local_appdata.join(
-                "AppInstaller").join("AppInstaller.exe");
-        if !local_installer_path.exists() {
-            add_message(&listview, "INFO", 
-                    "No local installer found. Downloading...");
-            get_installer(&listview, &bar);
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where the last "*" was and how much text it has swallowed so far.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Build metadata CI drops next to each zip as `build-info.json`.
+#[derive(Deserialize)]
+struct BuildInfo {
+    commit: Option<String>,
+    branch: Option<String>,
+    date: Option<String>,
+}
+
+fn report_build_info(reporter: &dyn ProgressReporter, source_dir: &Path) {
+    let info_path = source_dir.join("build-info.json");
+    let text = match fs::read_to_string(&info_path) {
+        Ok(text) => text,
+        Err(_) => {
+            add_message(reporter, LogLevel::Debug, 
+                    &format!("No build metadata found at {:?}", info_path));
             return;
         }
+    };
+    match serde_json::from_str::<BuildInfo>(&text) {
+        Ok(info) => {
+            let unknown = "unknown".to_string();
+            add_message(reporter, LogLevel::Info, &format!(
+                    "Build: commit {}, branch {}, built {}",
+                    info.commit.as_ref().unwrap_or(&unknown),
+                    info.branch.as_ref().unwrap_or(&unknown),
+                    info.date.as_ref().unwrap_or(&unknown)));
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Warn, 
+                    &format!("Failed to parse {:?}: {}", info_path, e));
+        }
+    }
+}
 
-        if let Ok(current_exe) = env::current_exe() {
-            if let Ok(local_meta) = fs::metadata(&current_exe) {
-                if let Ok(local_time) = local_meta.modified() {
-                    perform_installer_update(local_time, current_exe, 
-                            &listview, &bar);
-                }
+fn unzip_file(reporter: &dyn ProgressReporter, zip_file: &Path, app_name: &str) 
+        -> bool {
+    if let Some(extract_to_dir) = get_app_dir(reporter, app_name) {
+        let created = if dry_run() {
+            Ok(())
+        } else {
+            fs::create_dir_all(&extract_to_dir)
+        };
+        if let Err(e) = created {
+            add_message(reporter, LogLevel::Error,
+                &format!("Failed to create directory {:?}: {}", 
+                        extract_to_dir, e));
+            return false;
+        }
+
+        let file = match File::open(zip_file) {
+            Ok(f) => f,
+            Err(e) => {
+                add_message(reporter, LogLevel::Error, 
+                        &format!("Unable to open zip file: {}", e));
+                return false;
+            }
+        };
+        let mut reader = BufReader::new(file);
+
+        let extracted = match zip_utils::detect_format(&mut reader) {
+            Ok(ArchiveFormat::Zip) => extract_zip(reporter, &mut reader, 
+                    zip_file, &extract_to_dir),
+            #[cfg(feature = "sevenz")]
+            Ok(ArchiveFormat::SevenZip) => 
+                    extract_7z(reporter, zip_file, &extract_to_dir),
+            #[cfg(not(feature = "sevenz"))]
+            Ok(ArchiveFormat::SevenZip) => {
+                add_message(reporter, LogLevel::Error, &format!(
+                        "{:?} is a 7-Zip archive. Build the installer with \
+                        --features sevenz to install it.", zip_file));
+                None
+            }
+            Ok(format) => {
+                add_message(reporter, LogLevel::Error, &format!(
+                        "{:?} is not a zip or 7-Zip archive: it looks like {}.",
+                        zip_file, format));
+                None
+            }
+            Err(e) => {
+                add_message(reporter, LogLevel::Error, 
+                        &format!("Unable to read zip file: {}", e));
+                None
             }
+        };
+        let total = match extracted {
+            Some(total) => total,
+            None => return false,
+        };
+        reporter.progress(100, &format!("Extracted {} files (100%)", total));
+        if dry_run() {
+            add_message(reporter, LogLevel::Info, &format!(
+                    "{}Would unzip to '{:?}'", DRY_RUN_PREFIX, extract_to_dir));
+            return true;
         }
+        add_message(reporter, LogLevel::Info, &format!(
+                "Successfully unzipped to '{:?}'", extract_to_dir));
+        true
+    } else {
+        add_message(reporter, LogLevel::Error, 
+                "Could not find the install folder to unzip to.");
+        false
     }
 }
 
-fn perform_installer_update(local_time: SystemTime, current_exe: PathBuf, 
-        listview: &nwg::ListView, bar: &nwg::ProgressBar) {
-    let remote_dir = REMOTE_DIR.lock().unwrap().clone().join(
-            "AppInstaller");
-    let mut newest_remote_file: Option<(PathBuf, 
-            SystemTime)> = None;
-    if let Ok(entries) = fs::read_dir(&remote_dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file() && path.extension(
-                        ).and_then(|s| s.to_str()) == 
-                        Some("zip") {
-                    if let Ok(metadata) = 
-                            fs::metadata(&path) {
-                        if let Ok(modified) = 
-                                metadata.modified() {
-                            if newest_remote_file.is_none() || modified > 
-                                    newest_remote_file.as_ref().unwrap().1 {
-                                newest_remote_file = Some((path, modified));
-                            }
-                        }
-                    }
-                }
+/// Extracts (or with --dry-run lists) every entry of a zip. Files are 
+/// written by several threads at once (see extract_files); symlinks are 
+/// made last, once their targets exist. Returns the entry count, or None 
+/// once a failure has been logged.
+fn extract_zip(reporter: &dyn ProgressReporter, 
+        reader: &mut BufReader<File>, zip_file: &Path, extract_to_dir: &Path) 
+        -> Option<usize> {
+    reporter.busy("Reading the zip's contents");
+    let read = if *ALLOW_DUPLICATE_ENTRIES.lock().unwrap() {
+        zip_utils::read_central_directory_keep_first(reader)
+    } else {
+        zip_utils::read_central_directory(reader)
+            .map(|entries| (entries, Vec::new()))
+    };
+    let entries = match read {
+        Ok((entries, duplicates)) => {
+            for file_name in duplicates {
+                add_message(reporter, LogLevel::Warn, &format!(
+                        "Skipping a second entry named {}; the first one is \
+                        extracted.", file_name));
             }
+            entries
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, 
+                    &format!("Failed to parse zip file: {}", e));
+            return None;
+        }
+    };
+
+    let total = entries.len();
+    let (links, files): (Vec<_>, Vec<_>) = entries.iter()
+        .partition(|entry| entry.is_symlink());
+    if dry_run() {
+        for (index, entry) in files.iter().chain(links.iter()).enumerate() {
+            report_extract_progress(reporter, index, total, 
+                    (index * 100 / total) as u32);
+            add_message(reporter, LogLevel::Info, &format!(
+                    "{}Would extract file: {}", DRY_RUN_PREFIX, 
+                    entry.file_name));
         }
+        return Some(total);
     }
 
-    if let Some((_, remote_time)) = newest_remote_file {
-        if remote_time > local_time {
-            add_message(&listview, "INFO", 
-                    "Newer installer found. Updating...");
-            let new_name = current_exe.with_extension("AppInstaller.old");
-            if let Err(e) = fs::rename(&current_exe, &new_name) {
-                add_message(&listview, "ERROR",
-                    &format!("Failed to rename old installer: {}", e),
-                );
-                return;
-            }
-            get_installer(&listview, &bar);
-            add_message(&listview, "INFO", "Installer updated.");
-            //unsafe { PostQuitMessage(0); }
+    // Progress goes by bytes, so one large file moves the bar as it is 
+    // written rather than counting the same as a small one.
+    let bytes_total = total_size(&entries.iter().collect::<Vec<_>>());
+    if !create_folders(reporter, &files, extract_to_dir) {
+        return None;
+    }
+    let mut failed = !extract_files(reporter, zip_file, &files, 
+            extract_to_dir, total, bytes_total);
+    let mut bytes_done = total_size(&files);
+    for (index, entry) in links.iter().enumerate() {
+        if is_cancelled() {
+            return None;
         }
+        report_extract_progress(reporter, files.len() + index, total, 
+                percent(bytes_done, bytes_total));
+        add_message(reporter, LogLevel::Trace, &format!(
+                "Extracting file: {}", entry.file_name));
+        failed |= !extract_symlink(reporter, entry, reader, extract_to_dir);
+        bytes_done = bytes_done.saturating_add(entry.uncompressed_size);
+    }
+    if is_cancelled() {
+        return None;
     }
+    if failed { None } else { Some(total) }
 }
 
-fn check_if_running(process_name: &str) -> bool {
-    let s = System::new_all();
-    for _process in s.processes_by_name(process_name) {
-        return true;
+/// The entries' uncompressed sizes added up, at least 1 so it can divide. 
+/// Sizes come from the zip, so a damaged one mustn't overflow the sum.
+fn total_size(entries: &[&zip_utils::ZipEntry]) -> u64 {
+    entries.iter()
+        .fold(0u64, |total, entry| total.saturating_add(
+                entry.uncompressed_size))
+        .max(1)
+}
+
+/// `done` as a percentage of `total`, without overflowing for sizes near 
+/// u64::MAX.
+fn percent(done: u64, total: u64) -> u32 {
+    (done.min(total) as u128 * 100 / total.max(1) as u128) as u32
+}
+
+/// Creates the folders `files` go in, on this thread, so that the threads 
+/// extracting them never race to make the same one. Returns false once a 
+/// failure has been logged.
+fn create_folders(reporter: &dyn ProgressReporter, 
+        files: &[&zip_utils::ZipEntry], extract_to_dir: &Path) -> bool {
+    let mut created = HashSet::new();
+    for entry in files {
+        let path = match zip_utils::join_entry_path(extract_to_dir, 
+                &entry.file_name) {
+            Ok(path) => path,
+            Err(e) => {
+                add_message(reporter, LogLevel::Error, &format!(
+                        "Failed to extract {}: {}", entry.file_name, e));
+                return false;
+            }
+        };
+        let folder = if entry.file_name.ends_with('/') {
+            path
+        } else {
+            match path.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => continue,
+            }
+        };
+        if !created.insert(folder.clone()) {
+            continue;
+        }
+        let result = fs::create_dir_all(&folder).map_err(ZipError::from)
+            .and_then(|()| zip_utils::check_within(extract_to_dir, &folder, 
+                    &entry.file_name));
+        if let Err(e) = result {
+            add_message(reporter, LogLevel::Error, &format!(
+                    "Failed to create the folder for {}: {}", 
+                    entry.file_name, e));
+            return false;
+        }
     }
-    false
+    true
 }
 
-fn uninstall_application(listview: &nwg::ListView, app_name: &str) {
-    add_message(&listview, "DEBUG",
-        &format!("Attempting to uninstall application: {}", app_name));
-    let shortcut_name = add_spaces(app_name);
-    if let Some((shortcut_path, target_dir)) = find_shortcut(&shortcut_name) {
-        if target_dir.exists() {
-            if let Err(e) = fs::remove_dir_all(&target_dir) {
-                add_message(&listview, "ERROR",
-                    &format!("Failed to delete directory '{:?}': {}", 
-                            target_dir, e));
-            } else {
-                add_message(&listview, "DEBUG",
-                    &format!("Deleted existing directory at {:?}", 
-                            target_dir));
+/// The most threads extract_files uses, however many cores there are; past
+/// this the disk, not deflate, is the limit.
+const MAX_EXTRACT_THREADS: usize = 8;
+
+/// What a thread of extract_files reports about the entry it is writing.
+enum ExtractUpdate {
+    Started,
+    Written(u64),
+    Done(zip_utils::ZipResult<PathBuf>),
+}
+
+/// Extracts `files` on up to MAX_EXTRACT_THREADS threads, each reading the 
+/// zip through its own handle and taking the next entry when it finishes 
+/// one. The threads send their progress and results back over a channel,
+/// so only this thread uses the reporter. Returns false once a failure has
+/// been logged; like the other entries, a failed one doesn't stop the rest.
+fn extract_files(reporter: &dyn ProgressReporter, zip_file: &Path, 
+        files: &[&zip_utils::ZipEntry], extract_to_dir: &Path, total: usize, 
+        bytes_total: u64) -> bool {
+    let threads = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(MAX_EXTRACT_THREADS)
+        .min(files.len());
+    let readers = (0..threads)
+        .map(|_| File::open(zip_file).map(BufReader::new))
+        .collect::<io::Result<Vec<_>>>();
+    let readers = match readers {
+        Ok(readers) => readers,
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, 
+                    &format!("Unable to open zip file: {}", e));
+            return false;
+        }
+    };
+
+    let next_index = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut failed = false;
+    thread::scope(|scope| {
+        for mut reader in readers {
+            let sender = sender.clone();
+            let next_index = &next_index;
+            scope.spawn(move || loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                if index >= files.len() || is_cancelled() {
+                    break;
+                }
+                let _ = sender.send((index, ExtractUpdate::Started));
+                let result = zip_utils::extract_file_with_progress(
+                        files[index], &mut reader, extract_to_dir, 
+                        |written| {
+                    let _ = sender.send((index, 
+                            ExtractUpdate::Written(written)));
+                });
+                let _ = sender.send((index, ExtractUpdate::Done(result)));
+            });
+        }
+        // The loop below ends once every thread has dropped its sender.
+        drop(sender);
+
+        let mut written = vec![0u64; files.len()];
+        let mut bytes_done = 0u64;
+        let mut completed = 0;
+        let mut last_progress = None;
+        for (index, update) in receiver {
+            let entry = files[index];
+            match update {
+                ExtractUpdate::Started => add_message(reporter, 
+                        LogLevel::Trace, &format!("Extracting file: {}", 
+                        entry.file_name)),
+                ExtractUpdate::Written(bytes) => {
+                    bytes_done = bytes_done.saturating_add(
+                            bytes.saturating_sub(written[index]));
+                    written[index] = bytes;
+                    reporter.entry_progress(&entry.file_name, bytes, 
+                            entry.uncompressed_size);
+                }
+                ExtractUpdate::Done(result) => {
+                    // A failed entry counts as done for the bar too.
+                    bytes_done = bytes_done.saturating_add(entry
+                        .uncompressed_size.saturating_sub(written[index]));
+                    written[index] = entry.uncompressed_size;
+                    completed += 1;
+                    match result {
+                        Ok(path) => {
+                            if let Err(e) = zip_utils::set_modified_time(
+                                    entry, &path) {
+                                add_message(reporter, LogLevel::Warn, 
+                                        &format!("Could not set the time \
+                                        of {}: {}", entry.file_name, e));
+                            }
+                        }
+                        Err(e) => {
+                            add_message(reporter, LogLevel::Error, &format!(
+                                    "Failed to extract {}: {}", 
+                                    entry.file_name, e));
+                            failed = true;
+                        }
+                    }
+                }
+            }
+            let progress = percent(bytes_done, bytes_total);
+            if last_progress != Some(progress) {
+                last_progress = Some(progress);
+                report_extract_progress(reporter, 
+                        completed.min(total - 1), total, progress);
             }
         }
-        if let Err(e) = fs::remove_file(&shortcut_path) {
-            add_message(&listview, "ERROR",
-                &format!("Failed to delete shortcut '{:?}': {}", 
-                    shortcut_path, e));
-        } else {
-            add_message(&listview, "DEBUG", &format!("Deleted shortcut at {:?}", 
-                    shortcut_path));
+    });
+    !failed
+}
+
+/// Recreates a symlink entry, warning when it had to become a copy of its
+/// target. Returns false once a failure has been logged.
+fn extract_symlink(reporter: &dyn ProgressReporter, 
+        entry: &zip_utils::ZipEntry, reader: &mut BufReader<File>, 
+        extract_to_dir: &Path) -> bool {
+    match zip_utils::extract_symlink(entry, reader, extract_to_dir) {
+        Ok(LinkKind::Symlink) => true,
+        Ok(LinkKind::Junction) => {
+            add_message(reporter, LogLevel::Debug, &format!(
+                    "Made {} a junction, as symlinks are not allowed.", 
+                    entry.file_name));
+            true
         }
-    } else {
-        add_message(&listview, "DEBUG", &format!(
-                "No existing shortcut found. Checking default location."));
-        if let Some(local_appdata) = get_local_appdata(&listview) {
-            let dir_to_delete = local_appdata.join(app_name);
-            if dir_to_delete.exists() {
-                if let Err(e) = fs::remove_dir_all(&dir_to_delete) {
-                    add_message(&listview, "ERROR",
-                        &format!("Failed to delete directory '{:?}': {}", 
-                                dir_to_delete, e));
-                } else {
-                    add_message(&listview, "DEBUG",
-                        &format!("Deleted existing directory at {:?}", 
-                                dir_to_delete));
+        Ok(LinkKind::Copy) => {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Symlinks are not allowed for this account, so {} is a \
+                    copy of its target.", entry.file_name));
+            true
+        }
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, &format!(
+                    "Failed to create link {}: {}", entry.file_name, e));
+            false
+        }
+    }
+}
+
+/// The 7-Zip counterpart of extract_zip, with the same logging.
+#[cfg(feature = "sevenz")]
+fn extract_7z(reporter: &dyn ProgressReporter, archive_path: &Path, 
+        extract_to_dir: &Path) -> Option<usize> {
+    if dry_run() {
+        return match sevenz_utils::entry_names(archive_path) {
+            Ok(names) => {
+                for name in &names {
+                    add_message(reporter, LogLevel::Info, &format!(
+                            "{}Would extract file: {}", DRY_RUN_PREFIX, name));
                 }
+                Some(names.len())
+            }
+            Err(e) => {
+                add_message(reporter, LogLevel::Error, 
+                        &format!("Failed to parse 7-Zip archive: {}", e));
+                None
             }
+        };
+    }
+    let extracted = sevenz_utils::extract_all(archive_path, extract_to_dir, 
+            |index, total, name| {
+        if is_cancelled() {
+            return false;
+        }
+        report_extract_progress(reporter, index, total, 
+                (index * 100 / total) as u32);
+        add_message(reporter, LogLevel::Trace, 
+                &format!("Extracting file: {}", name));
+        true
+    });
+    match extracted {
+        Ok(total) => Some(total),
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => None,
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, 
+                    &format!("Failed to extract: {}", e));
+            None
         }
     }
 }
 
-fn copy_latest_zip(listview: &nwg::ListView, bar: &nwg::ProgressBar, 
-        app_name: &str) -> Option<PathBuf> {
-    let source_dir_path = REMOTE_DIR.lock().unwrap().clone().join(app_name);
-    add_message(&listview, "DEBUG",
-        &format!("Searching for zip files in {:?}", source_dir_path));
+fn report_extract_progress(reporter: &dyn ProgressReporter, index: usize, 
+        total: usize, progress: u32) {
+    reporter.progress(progress, &format!(
+            "Extracting file {} of {} ({}%)", index + 1, total, progress));
+}
 
-    let entries = match fs::read_dir(&source_dir_path) {
-        Ok(entries) => entries,
-        Err(e) => {
-            add_message(&listview, "ERROR", &format!(
-                    "Source directory not found or unreadable: {:?}: {}",
-                    source_dir_path, e));
-            return None;
+/// Free space an install must leave on the drive, so it never fills it.
+const DISK_SPACE_MARGIN: u64 = 100 * 1024 * 1024;
+
+/// Checks, before the old version is removed, that the drive has room for 
+/// the zip's uncompressed contents plus DISK_SPACE_MARGIN. The old version's
+/// folder counts as free, since it is deleted first. Fails with 
+/// NotEnoughSpace, having logged why, or with Cancelled when Cancel is 
+/// pressed while the zip or the old folder is measured.
+fn check_disk_space(reporter: &dyn ProgressReporter, zip_path: &Path, 
+        app_name: &str) -> Result<(), InstallError> {
+    let totals = match zip_totals(zip_path) {
+        Some(totals) => totals,
+        // unzip_file reports an unreadable zip.
+        None => return Ok(()),
+    };
+    if is_cancelled() {
+        return Err(InstallError::Cancelled);
+    }
+    add_message(reporter, LogLevel::Info, &format!(
+            "Installing {} files, {} on disk ({} compressed).", totals.files, 
+            format_size(totals.uncompressed), format_size(totals.compressed)));
+    let needed = totals.uncompressed;
+    let app_dir = match get_app_dir(reporter, app_name) {
+        Some(app_dir) => app_dir,
+        None => return Ok(()),
+    };
+    let free = match free_disk_space(&app_dir) {
+        Some(free) => free,
+        None => {
+            add_message(reporter, LogLevel::Debug, &format!(
+                    "Could not get the free space for {:?}", app_dir));
+            return Ok(());
         }
     };
+    // A large old install takes a while to measure.
+    let reclaimed = dir_size(&app_dir);
+    if is_cancelled() {
+        return Err(InstallError::Cancelled);
+    }
+    add_message(reporter, LogLevel::Debug, &format!(
+            "{} free on the target drive.", format_size(free)));
+    let needed = needed.saturating_add(DISK_SPACE_MARGIN);
+    let available = free.saturating_add(reclaimed);
+    if needed > available {
+        add_message(reporter, LogLevel::Error, &format!(
+                "Not enough disk space for {:?}: {} needed (with {} to \
+                spare), {} available.", app_dir, format_size(needed), 
+                format_size(DISK_SPACE_MARGIN), format_size(available)));
+        return Err(InstallError::NotEnoughSpace);
+    }
+    Ok(())
+}
+
+/// The number of files in a zip and their total compressed and uncompressed
+/// sizes, from its central directory.
+struct ZipTotals {
+    files: usize,
+    compressed: u64,
+    uncompressed: u64,
+}
+
+/// None for an archive that isn't a readable zip, such as a 7-Zip one.
+fn zip_totals(zip_path: &Path) -> Option<ZipTotals> {
+    let mut reader = BufReader::new(File::open(zip_path).ok()?);
+    let (entries, _) = zip_utils::read_central_directory_keep_first(
+            &mut reader).ok()?;
+    // Sizes come from the zip, so a damaged one mustn't overflow the sums.
+    Some(entries.iter()
+        .filter(|entry| !entry.file_name.ends_with('/'))
+        .fold(ZipTotals { files: 0, compressed: 0, uncompressed: 0 }, 
+                |totals, entry| ZipTotals {
+            files: totals.files + 1,
+            compressed: totals.compressed.saturating_add(
+                    entry.compressed_size),
+            uncompressed: totals.uncompressed.saturating_add(
+                    entry.uncompressed_size),
+        }))
+}
+
+/// The percentage of the progress bar for copying `zip_path`, weighing the
+/// bytes copied against the bytes the extraction will write. None when the 
+/// zip's sizes can't be read.
+fn copy_progress_share(zip_path: &Path) -> Option<u32> {
+    let zip_size = fs::metadata(zip_path).ok()?.len();
+    let total = zip_size.saturating_add(zip_totals(zip_path)?.uncompressed)
+        .max(1);
+    Some((zip_size as u128 * 100 / total as u128) as u32)
+}
 
-    let mut newest_file: Option<(PathBuf, SystemTime)> = None;
+/// Bytes available to this user on the drive holding `path`, which need 
+/// not exist yet.
+fn free_disk_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|dir| dir.exists())?;
+    let wide_path: Vec<u16> = existing.as_os_str().encode_wide()
+        .chain(Some(0)).collect();
+    let mut free: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(wide_path.as_ptr(), &mut free, 
+                std::ptr::null_mut(), std::ptr::null_mut())
+    };
+    if ok == 0 {
+        return None;
+    }
+    Some(unsafe { *free.QuadPart() })
+}
 
-    for entry in entries {
-        if let Ok(entry) = entry {
+fn report_install_size(reporter: &dyn ProgressReporter, app_dir: &Path) {
+    if !app_dir.exists() {
+        return;
+    }
+    if let Ok(entries) = fs::read_dir(app_dir) {
+        for entry in entries.flatten() {
             let path = entry.path();
-            if path.is_file()
-                && path.extension().and_then(|s| s.to_str()) == Some("zip")
-            {
-                if let Ok(metadata) = fs::metadata(&path) {
-                    if let Ok(modified) = metadata.modified() {
-                        if newest_file.is_none() ||
-                            modified > newest_file.as_ref().unwrap().1 {
-                            newest_file = Some((path, modified));
-                        }
-                    }
-                }
+            if path.is_dir() {
+                add_message(reporter, LogLevel::Debug, &format!("{:?}: {}", 
+                        entry.file_name(), format_size(dir_size(&path))));
             }
         }
     }
+    add_message(reporter, LogLevel::Info, &format!("Installed size: {}", 
+            format_size(dir_size(app_dir))));
+}
 
-    if let Some((newest_file_path, _)) = newest_file.clone() {
-        add_message(&listview, "DEBUG",
-            &format!("Found latest zip file: {:?}", newest_file_path));
-        if let Some(local_appdata) = get_local_appdata(&listview) {
-            let file_name = match newest_file_path.file_name() {
-                Some(name) => name,
-                None => {
-                    add_message(&listview, "ERROR",
-                            "Could not get file name from path."); 
-                    return None;
-                }
-            };
-            let dest_path = local_appdata.join(file_name);
-
-            //ui::show_progress();
-            let result = copy_with_progress(&bar, 
-                        &newest_file_path, &dest_path);
-	    //ui::hide_progress();
-
-            match result {
-                Ok(_) => {
-                    add_message(&listview, "DEBUG", &format!(
-                            "Copied latest version {:?} to {:?}", 
-                            file_name, dest_path)); 
-                    return Some(dest_path);
+/// Sums the sizes of all files below `dir`. Unreadable entries are skipped
+/// so a partially extracted directory still reports what is present.
+fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0;
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if let Ok(file_type) = entry.file_type() {
+                if file_type.is_dir() {
+                    total += dir_size(&entry.path());
+                } else if let Ok(metadata) = entry.metadata() {
+                    total += metadata.len();
                 }
-                Err(e) => {
-                    add_message(&listview, "ERROR", 
-                        &format!("Error copying file: {}", e));
-                    return None;
-                },
             }
-        } else {
-            add_message(&listview, "ERROR", 
-                    "Could not find LOCALAPPDATA directory.");
         }
-    } else {
-        add_message(&listview, "ERROR", 
-                &format!("No .zip files found in {:?}", source_dir_path)); 
     }
-    None
+    total
 }
 
-fn update_progress(bar: &nwg::ProgressBar, progress: u32) {
-    if progress < 100 {
-        bar.set_pos(progress);
-    } else {
-        bar.set_pos(0);
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
     }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
 }
 
-fn unzip_file(listview: &nwg::ListView, zip_file: &Path, app_name: &str) {
-    if let Some(local_appdata) = get_local_appdata(&listview) {
-        let extract_to_dir = local_appdata.join(app_name);
-        if let Err(e) = fs::create_dir_all(&extract_to_dir) {
-            add_message(&listview, "ERROR",
-                &format!("Failed to create directory {:?}: {}", 
-                        extract_to_dir, e));
-            return;
-        }
-
-        let mut file = match File::open(zip_file) {
-            Ok(f) => f,
-            Err(e) => {
-                add_message(&listview, "ERROR", 
-                        &format!("Unable to open zip file: {}", e));
-                return;
-            }
-        };
+/// Anything smaller can't be a real Windows program; a truncated copy often 
+/// is.
+const MIN_EXECUTABLE_SIZE: u64 = 1024;
 
-        let mut buffer = Vec::new();
-        if let Err(e) = file.read_to_end(&mut buffer) {
-            add_message(&listview, "ERROR", &format!(
-                    "Unable to read zip file: {}", e));
-            return;
-        }
+/// Checks that `path` is a plausible Windows executable: a file of at least
+/// MIN_EXECUTABLE_SIZE bytes starting with the `MZ` signature.
+pub fn verify_executable(path: &Path) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if size < MIN_EXECUTABLE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, 
+                format!("only {} bytes long", size)));
+    }
+    let mut signature = [0u8; 2];
+    file.read_exact(&mut signature)?;
+    if &signature != b"MZ" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, 
+                "no MZ signature"));
+    }
+    Ok(())
+}
 
-        let entries = match zip_utils::parse_central_directory(&buffer) {
+/// Picks the app's main executable: an exe named after the app (with or 
+/// without add_spaces, ignoring case) first, then the largest exe, then the
+/// first by path. Looks in the app folder and one level of subfolders, 
+/// since some apps keep their binary under bin\.
+fn find_executable(dir: &Path, app_name: &str) -> Option<PathBuf> {
+    let names = [app_name.to_lowercase(), add_spaces(app_name).to_lowercase()];
+    let mut candidates: Vec<(bool, u64, PathBuf)> = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    if let Ok(entries) = fs::read_dir(dir) {
+        dirs.extend(entries.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir()));
+    }
+    for dir in dirs {
+        let entries = match fs::read_dir(&dir) {
             Ok(entries) => entries,
-            Err(e) => {
-                add_message(&listview, "ERROR", 
-                        &format!("Failed to parse zip file: {}", e));
-                return;
-            }
+            Err(_) => continue,
         };
-
-        for entry in &entries {
-            add_message(&listview, "INFO", &format!("Extracting file: {}", 
-                    entry.file_name));
-            if let Err(e) = zip_utils::extract_file(entry, &buffer, 
-                    &extract_to_dir) {
-                add_message( &listview, "ERROR",
-                    &format!("Failed to extract {}: {}", entry.file_name, e));
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_exe = path.extension().and_then(|s| s.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"));
+            if !is_exe || !path.is_file() {
+                continue;
             }
+            let name_matches = path.file_stem().and_then(|s| s.to_str())
+                .is_some_and(|stem| names.contains(&stem.to_lowercase()));
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            candidates.push((name_matches, size, path));
         }
+    }
+    candidates.into_iter()
+        .min_by(|a, b| b.0.cmp(&a.0)
+            .then(b.1.cmp(&a.1))
+            .then(a.2.cmp(&b.2)))
+        .map(|(_, _, path)| path)
+}
 
-        add_message( &listview, "INFO", &format!(
-                "Successfully unzipped to '{:?}'", extract_to_dir));
+/// Creates the app's Start Menu shortcut (and the Desktop one with 
+/// --desktop-shortcut). `arguments` is written into the link verbatim, so an
+/// argument containing spaces must carry its own quotes. Without `icon` the
+/// link shows the exe's icon.
+fn create_shortcut(reporter: &dyn ProgressReporter, executable_path: &str, 
+            shortcut_name: &str, arguments: Option<&str>, 
+            working_dir: Option<&Path>, icon: Option<&Path>, desktop: bool) {
+    // The per-user Start Menu under LOCALAPPDATA, when there is one. A
+    // user or share whose name contains "Local" mustn't change the choice.
+    // An all-users install uses the common one.
+    let start_menu_paths = get_start_menu_paths();
+    let local_appdata = get_local_appdata_root()
+        .map(|dir| comparable_path(&dir));
+    let start_menu = if all_users() {
+        get_common_start_menu_path()
     } else {
-        add_message(&listview, "ERROR", 
-                "Could not find LOCALAPPDATA to unzip.");
+        start_menu_paths
+            .iter()
+            .find(|p| local_appdata.as_ref()
+                    .is_some_and(|dir| comparable_path(p).starts_with(dir)))
+            .or_else(|| start_menu_paths.first())
+            .cloned()
+    };
+    if let Some(start_menu) = start_menu { 
+        write_shortcut(reporter, executable_path, 
+                &start_menu.join(format!("{}.lnk", shortcut_name)), 
+                arguments, working_dir, icon);
+    } else {
+        add_message(reporter, LogLevel::Error, 
+                "Could not find Start Menu path.");
     }
-}
 
-fn find_executable(dir: &Path) -> Option<PathBuf> {
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file()
-                    && path.extension().and_then(|s| s.to_str()) == Some("exe")
-                {
-                    return Some(path);
-                }
-            }
+    if desktop {
+        let desktop_path = if all_users() {
+            get_public_desktop_path()
+        } else {
+            get_desktop_path()
+        };
+        match desktop_path {
+            Some(desktop) => write_shortcut(reporter, executable_path, 
+                    &desktop.join(format!("{}.lnk", shortcut_name)), 
+                    arguments, working_dir, icon),
+            None => add_message(reporter, LogLevel::Error, 
+                    "Could not find Desktop path."),
         }
     }
-    None
 }
 
-fn create_shortcut(listview: &nwg::ListView, executable_path: &str, 
-            shortcut_name: &str) {
-    let start_menu_paths = get_start_menu_paths();
-    if let Some(start_menu) = start_menu_paths
-        .iter()
-        .find(|p| p.to_str().unwrap_or("").contains("Local"))
-        .or_else(|| start_menu_paths.first()) { 
-        let shortcut_name_with_spaces = add_spaces(shortcut_name);
-        let shortcut_path = start_menu.join(format!(
-            "{}.lnk",
-            shortcut_name_with_spaces
-        ));
-        if shortcut_path.exists() {
-            if let Err(e) = fs::remove_file(&shortcut_path) {
-                add_message(&listview, "ERROR",
-                    &format!("Failed to delete existing shortcut: {}", e));
-            }
-        }
-
-        let sl = match ShellLink::new(executable_path) {
-            Ok(link) => link,
-            Err(e) => {
-                add_message(&listview, "ERROR",
-                    &format!("Failed to create shell link: {}", e));
-                return;
-            }
-        };
+fn write_shortcut(reporter: &dyn ProgressReporter, executable_path: &str, 
+        shortcut_path: &Path, arguments: Option<&str>, 
+        working_dir: Option<&Path>, icon: Option<&Path>) {
+    if dry_run() {
+        add_message(reporter, LogLevel::Info, &format!(
+                "{}Would create shortcut at {:?} for {}", DRY_RUN_PREFIX, 
+                shortcut_path, executable_path));
+        return;
+    }
+    if shortcut_path.exists() && 
+            let Err(e) = fs::remove_file(shortcut_path) {
+        add_message(reporter, LogLevel::Warn,
+            &format!("Failed to delete existing shortcut: {}", e));
+    }
 
-        if let Err(e) = sl.create_lnk(&shortcut_path) {
-            add_message(&listview, "ERROR", &format!(
-                    "Failed to create shortcut: {}", e));
-        } else {
-            add_message(&listview, "DEBUG", 
-                    &format!("Shortcut created at {:?}", shortcut_path));
+    let mut sl = match ShellLink::new(executable_path) {
+        Ok(link) => link,
+        Err(e) => {
+            add_message(reporter, LogLevel::Error,
+                &format!("Failed to create shell link: {}", e));
+            return;
         }
+    };
+    // mslnk's target ID list assumes a drive letter, so a link to an exe on
+    // a share keeps only the full path, which the shell resolves as is.
+    if is_unc(Path::new(executable_path)) {
+        sl.header_mut().update_link_flags(LinkFlags::HAS_LINK_TARGET_ID_LIST, 
+                false);
+        sl.set_relative_path(Some(executable_path.to_string()));
+    }
+    sl.set_arguments(arguments.map(str::to_string));
+    sl.set_working_dir(working_dir.map(|dir| dir.to_string_lossy()
+            .into_owned()));
+    sl.set_icon_location(icon.map(|icon| icon.to_string_lossy()
+            .into_owned()));
+
+    if let Err(e) = sl.create_lnk(shortcut_path) {
+        add_message(reporter, LogLevel::Error, &format!(
+                "Failed to create shortcut: {}", e));
     } else {
-        add_message(&listview, "ERROR", "Could not find Start Menu path.");
+        add_message(reporter, LogLevel::Debug, 
+                &format!("Shortcut created at {:?}", shortcut_path));
+    }
+}
+
+/// The LOCALAPPDATA known folder, or None if the shell can't provide it.
+pub fn get_local_appdata_root() -> Option<PathBuf> {
+    get_known_folder(&FOLDERID_LocalAppData)
+}
+
+fn get_desktop_path() -> Option<PathBuf> {
+    get_known_folder(&FOLDERID_Desktop)
+}
+
+/// The Desktop every user sees, for --all-users.
+fn get_public_desktop_path() -> Option<PathBuf> {
+    get_known_folder(&FOLDERID_PublicDesktop)
+}
+
+/// The Start Menu shared by all users, for --all-users.
+fn get_common_start_menu_path() -> Option<PathBuf> {
+    get_special_folder(CSIDL_COMMON_STARTMENU)
+}
+
+fn get_special_folder(csidl: c_int) -> Option<PathBuf> {
+    let mut path_buf = [0u16; 300];
+    let found = unsafe {
+        SHGetSpecialFolderPathW(
+            std::ptr::null_mut(),
+            path_buf.as_mut_ptr(),
+            csidl,
+            0
+        ) != 0
+    };
+    if !found {
+        return None;
     }
+    let path_str = String::from_utf16_lossy(&path_buf);
+    Some(PathBuf::from(path_str.trim_end_matches('\0')))
 }
 
-fn get_local_appdata_root() -> Option<PathBuf> {
+fn get_known_folder(folder_id: REFKNOWNFOLDERID) -> Option<PathBuf> {
     let mut path_ptr: PWSTR = std::ptr::null_mut();
     let result = unsafe { 
         SHGetKnownFolderPath(
-            &FOLDERID_LocalAppData,
+            folder_id,
             0,
             std::ptr::null_mut(),
             &mut path_ptr
         ) 
     };
-    if result == S_OK {
+    let path = if result == S_OK {
         let len = unsafe { 
             (0..).take_while(|&i| *path_ptr.offset(i) != 0).count() 
         };
@@ -469,25 +3521,17 @@ fn get_local_appdata_root() -> Option<PathBuf> {
         Some(PathBuf::from(os_string))
     } else {
         None
-    }
+    };
+    // The buffer is ours to free, even when the call failed.
+    unsafe { CoTaskMemFree(path_ptr as LPVOID) };
+    path
 }
 
 fn get_start_menu_paths() -> Vec<PathBuf> {
-    let mut paths = Vec::new();
-
-    let mut path_buf = [0u16; 300];
-    unsafe {
-        if SHGetSpecialFolderPathW(
-            std::ptr::null_mut(),
-            path_buf.as_mut_ptr(),
-            CSIDL_STARTMENU,
-            0
-        ) != 0 {
-            let path_str = String::from_utf16_lossy(&path_buf);
-            let path_str = path_str.trim_end_matches('\0');
-            paths.push(PathBuf::from(path_str));
-        }
-    }
+    let mut paths: Vec<PathBuf> = [CSIDL_STARTMENU, CSIDL_COMMON_STARTMENU]
+        .into_iter()
+        .filter_map(get_special_folder)
+        .collect();
 
     if let Some(mut local_appdata) = get_local_appdata_root() {
         local_appdata.push(r"Microsoft\Windows\Start Menu\Programs");
@@ -498,20 +3542,122 @@ fn get_start_menu_paths() -> Vec<PathBuf> {
     paths
 }
 
-fn get_installer(listview: &nwg::ListView, bar: &nwg::ProgressBar) {
-    if let Some(copied_zip_path) = copy_latest_zip(&listview, &bar, 
+fn get_installer(reporter: &dyn ProgressReporter) -> bool {
+    let remote_dir = REMOTE_DIR.lock().unwrap().clone().join("AppInstaller");
+    let zip_time = find_newest_zip(&remote_dir).ok().flatten()
+        .map(|(_, modified)| unix_seconds(modified));
+    if let Ok((zip_path, extract_from, copied)) = copy_latest_zip(reporter, 
             "AppInstaller") {
-        unzip_file(&listview, &copied_zip_path, "AppInstaller");
-        if let Err(e) = fs::remove_file(&copied_zip_path) {
-            add_message(&listview, "ERROR",
+        let extract_reporter = ProgressSpan { reporter, start: extract_from, 
+                end: 100 };
+        let unzipped = unzip_file(&extract_reporter, &zip_path, 
+                "AppInstaller");
+        if copied && let Err(e) = fs::remove_file(&zip_path) {
+            add_message(reporter, LogLevel::Warn,
                 &format!("Failed to delete installer zip file: {}", e));
         }
+        if let Some(zip_time) = zip_time.filter(|_| unzipped) {
+            write_installer_zip_time(reporter, zip_time);
+        }
+        unzipped
     } else {
-        add_message(&listview, "ERROR", "Failed to download installer.");
+        add_message(reporter, LogLevel::Warn, "Failed to download installer.");
+        false
+    }
+}
+
+fn write_installer_zip_time(reporter: &dyn ProgressReporter, zip_time: u64) {
+    if *DRY_RUN.lock().unwrap() {
+        return;
+    }
+    if let Some(installer_dir) = get_app_dir(reporter, "AppInstaller") {
+        let time_path = installer_dir.join(INSTALLER_ZIP_TIME_FILE_NAME);
+        if let Err(e) = fs::write(&time_path, zip_time.to_string()) {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "Failed to write {:?}: {}", time_path, e));
+        }
+    }
+}
+
+/// The name used for the app's shortcuts: the manifest's display_name, else
+/// the exe's ProductName (or FileDescription) when it has one, otherwise 
+/// add_spaces(app_name).
+fn shortcut_display_name(exe_path: &Path, app_name: &str, 
+        manifest: &AppManifest) -> String {
+    manifest.display_name.clone()
+        .or_else(|| version_utils::product_name(exe_path))
+        .map(|name| shortcut_file_name(&name))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| add_spaces(app_name))
+}
+
+/// `name` without the characters a file name can't hold.
+fn shortcut_file_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | 
+                '<' | '>' | '|'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// The manifest's icon, or `<exe name>.ico` beside the exe, if the file is
+/// there. None leaves the shortcut with the exe's icon.
+fn shortcut_icon(reporter: &dyn ProgressReporter, app_dir: &Path, 
+        exe_path: &Path, manifest: &AppManifest) -> Option<PathBuf> {
+    if let Some(icon) = &manifest.icon {
+        match zip_utils::join_entry_path(app_dir, &icon.replace('/', "\\")) {
+            Ok(icon_path) if icon_path.is_file() => return Some(icon_path),
+            Ok(icon_path) => add_message(reporter, LogLevel::Debug, &format!(
+                    "Icon {:?} not found; using the default icon.", 
+                    icon_path)),
+            Err(e) => add_message(reporter, LogLevel::Warn, &format!(
+                    "Icon {:?} can't be used: {}. Using the default icon.", 
+                    icon, e)),
+        }
     }
+    Some(exe_path.with_extension("ico")).filter(|path| path.is_file())
+}
+
+/// --shortcut-args when given, otherwise the manifest's launch_args.
+fn shortcut_arguments(manifest: &AppManifest) -> Option<String> {
+    SHORTCUT_ARGS.lock().unwrap().clone()
+        .or_else(|| manifest.launch_args.clone())
+}
+
+fn desktop_shortcut(manifest: &AppManifest) -> bool {
+    *DESKTOP_SHORTCUT.lock().unwrap() || manifest.create_desktop_shortcut
 }
 
+/// Splits a CamelCase app name into words: before a capital that follows a
+/// lowercase letter ("MyApp" -> "My App"), before the last capital of a run
+/// that starts a TitleCase word ("APIClient" -> "API Client"), and around 
+/// numbers ("App2Go" -> "App 2 Go"). A number after a capital stays with it,
+/// so "MP3Player" becomes "MP3 Player" and "V2" is unchanged.
 fn add_spaces(app_name: &str) -> String {
+    let chars: Vec<char> = app_name.chars().collect();
+    let mut new_name = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lowercase = chars.get(i + 1)
+                .is_some_and(|next| next.is_lowercase());
+            let word_start = c.is_uppercase() && (prev.is_lowercase() || 
+                    ((prev.is_uppercase() || prev.is_ascii_digit()) && 
+                    next_is_lowercase));
+            let number_start = c.is_ascii_digit() && prev.is_lowercase();
+            if word_start || number_start {
+                new_name.push(' ');
+            }
+        }
+        new_name.push(c);
+    }
+    new_name
+}
+
+/// The add_spaces of earlier versions, which only split before a capital 
+/// following a lowercase letter. Still used to find shortcuts it named.
+fn legacy_add_spaces(app_name: &str) -> String {
     let mut new_name = String::new();
     let mut last_char_was_lowercase = false;
 
@@ -525,35 +3671,261 @@ fn add_spaces(app_name: &str) -> String {
     new_name
 }
 
-fn find_shortcut(shortcut_name: &str) -> Option<(PathBuf, PathBuf)> {
-    for start_menu in get_start_menu_paths() {
-        let shortcut_path = start_menu.join(format!("{}.lnk", shortcut_name));
-        if shortcut_path.exists() {
-            if let Ok(file) = File::open(&shortcut_path) {
-                let mut reader = BufReader::new(file);
-                if let Ok(link) = Lnk::new(&mut reader) {
-                    if let Some(target) = link.link_info.local_base_path {
-                        let target_path = PathBuf::from(target);
-                        if let Some(parent) = target_path.parent() {
-                            return Some((shortcut_path, parent.to_path_buf()));
-                        }
-                    }
-                }
+/// Finds the app's shortcut in every Start Menu scope (per-user and common)
+/// and on the Desktop. Each match is returned with the directory its target
+/// lives in, when the link can be resolved.
+fn find_shortcuts(shortcut_name: &str) -> Vec<(PathBuf, Option<PathBuf>)> {
+    let mut shortcut_dirs = get_start_menu_paths();
+    shortcut_dirs.extend(get_desktop_path());
+    shortcut_dirs.extend(get_public_desktop_path());
+    find_shortcuts_in(&shortcut_dirs, shortcut_name)
+}
+
+/// The `<shortcut_name>.lnk` in each of `dirs`. Every scope that has one 
+/// gives its own match, but a folder listed twice, perhaps spelt 
+/// differently, is searched once, so the same file isn't counted and 
+/// deleted twice.
+fn find_shortcuts_in(dirs: &[PathBuf], shortcut_name: &str) 
+        -> Vec<(PathBuf, Option<PathBuf>)> {
+    let mut searched = HashSet::new();
+    dirs.iter()
+        .filter(|dir| searched.insert(comparable_path(dir)))
+        .map(|dir| dir.join(format!("{}.lnk", shortcut_name)))
+        .filter(|shortcut_path| shortcut_path.exists())
+        .map(|shortcut_path| {
+            let target_dir = resolve_shortcut_dir(&shortcut_path);
+            (shortcut_path, target_dir)
+        })
+        .collect()
+}
+
+/// Deletes one shortcut found by find_shortcuts, reporting it. Returns true
+/// if it was (or, in a dry run, would be) removed.
+fn remove_shortcut(reporter: &dyn ProgressReporter, shortcut_path: &Path) 
+        -> bool {
+    if dry_run() {
+        add_message(reporter, LogLevel::Info, &format!(
+                "{}Would delete shortcut at {:?}", DRY_RUN_PREFIX, 
+                shortcut_path));
+        true
+    } else if let Err(e) = fs::remove_file(shortcut_path) {
+        add_message(reporter, LogLevel::Error,
+            &format!("Failed to delete shortcut '{:?}': {}", 
+                shortcut_path, e));
+        false
+    } else {
+        add_message(reporter, LogLevel::Info, &format!(
+                "Deleted shortcut at {:?}", shortcut_path));
+        true
+    }
+}
+
+/// Maps the folder a shortcut's exe is in to the app folder to delete. Below
+/// an install root that is the root's subfolder holding the exe, which may 
+/// sit deeper (bin\). The root itself is never an app folder. Elsewhere the 
+/// exe's folder is used as it is.
+fn shortcut_app_dir(target_dir: PathBuf, roots: &[PathBuf]) -> Option<PathBuf> {
+    // Paths from a shortcut needn't match the root's case or form.
+    let target = comparable_path(&target_dir);
+    for root in roots {
+        if let Ok(rest) = target.strip_prefix(comparable_path(root)) {
+            if rest.as_os_str().is_empty() {
+                return None;
             }
+            let depth = root.components().count() + 1;
+            return Some(target_dir.components().take(depth).collect());
+        }
+    }
+    Some(target_dir)
+}
+
+/// The folder of a shortcut's target. A link may store the target as a 
+/// local path, in its environment variable block (`%LOCALAPPDATA%\...`) or 
+/// as a path relative to the shortcut; when they disagree, the first that 
+/// is absolute once variables are expanded wins, in that order. Links to an
+/// exe on a share carry its full path as the relative path (see 
+/// write_shortcut).
+fn resolve_shortcut_dir(shortcut_path: &Path) -> Option<PathBuf> {
+    let data = fs::read(shortcut_path).ok()?;
+    let link = Lnk::new(&mut io::Cursor::new(&data)).ok()?;
+    let candidates: Vec<PathBuf> = [
+        link.link_info.local_base_path.clone(),
+        environment_block_target(&data),
+        link.relative_path().map(|path| path.to_string_lossy().into_owned()),
+    ].into_iter()
+        .flatten()
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(expand_environment_strings(&path)))
+        .collect();
+    let target_path = match candidates.iter().find(|path| path.is_absolute()) {
+        Some(path) => path.clone(),
+        None => normalize_path(&shortcut_path.parent()?
+                .join(candidates.first()?)),
+    };
+    target_path.parent().map(Path::to_path_buf)
+}
+
+/// The size and signature that start a link's EnvironmentVariableDataBlock.
+const ENVIRONMENT_BLOCK_HEADER: [u8; 8] = 
+        [0x14, 0x03, 0x00, 0x00, 0x01, 0x00, 0x00, 0xa0];
+
+/// The target stored in a link's EnvironmentVariableDataBlock, with its 
+/// variables unexpanded. parselnk reads the block but doesn't expose it, so
+/// it is found in the raw bytes: a 260-byte ANSI path followed by a 
+/// 520-byte UTF-16 one, either of which may be empty.
+fn environment_block_target(data: &[u8]) -> Option<String> {
+    let start = data.windows(ENVIRONMENT_BLOCK_HEADER.len())
+        .position(|window| window == ENVIRONMENT_BLOCK_HEADER)? + 
+                ENVIRONMENT_BLOCK_HEADER.len();
+    let block = data.get(start..start + 260 + 520)?;
+    let (ansi, unicode) = block.split_at(260);
+    let wide: Vec<u16> = unicode.chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|&c| c != 0)
+        .collect();
+    if !wide.is_empty() {
+        return Some(String::from_utf16_lossy(&wide));
+    }
+    let ansi: Vec<u8> = ansi.iter().copied().take_while(|&c| c != 0)
+        .collect();
+    Some(String::from_utf8_lossy(&ansi).into_owned())
+}
+
+/// Replaces `%NAME%` variables with their values, as the shell does when it
+/// follows a link. Unknown variables are left as they are.
+fn expand_environment_strings(text: &str) -> String {
+    let wide_text: Vec<u16> = OsStr::new(text).encode_wide()
+        .chain(Some(0)).collect();
+    let needed = unsafe { 
+        ExpandEnvironmentStringsW(wide_text.as_ptr(), std::ptr::null_mut(), 
+                0) 
+    };
+    if needed == 0 {
+        return text.to_string();
+    }
+    let mut buffer = vec![0u16; needed as usize];
+    let written = unsafe { 
+        ExpandEnvironmentStringsW(wide_text.as_ptr(), buffer.as_mut_ptr(), 
+                needed) 
+    };
+    if written == 0 || written > needed {
+        return text.to_string();
+    }
+    // The count includes the terminating null.
+    OsString::from_wide(&buffer[..written as usize - 1])
+        .to_string_lossy().into_owned()
+}
+
+/// Drops `.` and resolves `..` in `path` without touching the disk, since 
+/// the target of a stale shortcut may no longer exist.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// With --verify-checksum, compares the SHA-256 of `zip_path` (the copy) 
+/// with the one published next to `source_zip` as `<zip name>.sha256`, 
+/// either the bare hash or a `sha256sum` line. Without a sidecar the zip is
+/// accepted with a warning. Returns false on a mismatch.
+fn verify_checksum(reporter: &dyn ProgressReporter, source_zip: &Path, 
+        zip_path: &Path) -> bool {
+    if !*VERIFY_CHECKSUM.lock().unwrap() {
+        return true;
+    }
+    let mut sidecar = source_zip.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    let sidecar = PathBuf::from(sidecar);
+    let expected = match fs::read_to_string(&sidecar) {
+        Ok(text) => text.split_whitespace().next().unwrap_or("")
+            .to_lowercase(),
+        Err(_) => {
+            add_message(reporter, LogLevel::Warn, &format!(
+                    "No checksum file {:?}; the zip was not verified.", 
+                    sidecar));
+            return true;
+        }
+    };
+    let actual = match sha256_hex(zip_path) {
+        Ok(actual) => actual,
+        Err(e) => {
+            add_message(reporter, LogLevel::Error, &format!(
+                    "Failed to compute the checksum of {:?}: {}", zip_path, e));
+            return false;
+        }
+    };
+    if actual != expected {
+        add_message(reporter, LogLevel::Error, &format!(
+                "Checksum mismatch for {:?}: expected {}, got {}", 
+                source_zip, expected, actual));
+        return false;
+    }
+    add_message(reporter, LogLevel::Info, "Zip checksum verified.");
+    true
+}
+
+fn sha256_hex(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Copies the zip, retrying with exponential backoff (1s, 2s, 4s, ...) so a
+/// sharing violation while CI is still writing the file doesn't abort the 
+/// install.
+fn copy_with_retries(reporter: &dyn ProgressReporter, from: &Path, 
+        to: &Path) -> io::Result<()> {
+    let retries = *COPY_RETRIES.lock().unwrap();
+    let mut attempt = 0;
+    loop {
+        match copy_with_progress(reporter, from, to) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < retries && !is_cancelled() => {
+                let delay = Duration::from_secs(1 << attempt.min(6));
+                attempt += 1;
+                add_message(reporter, LogLevel::Warn, &format!(
+                        "Copy failed ({}), retrying in {}s (attempt {} of {})",
+                        e, delay.as_secs(), attempt + 1, retries + 1));
+                thread::sleep(delay);
+            }
+            Err(e) => return Err(e),
         }
     }
-    None
 }
 
-fn copy_with_progress(bar: &nwg::ProgressBar, from: &Path, to: &Path) -> 
-        io::Result<()> {
+/// The least time between two progress reports from one copy.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+fn copy_with_progress(reporter: &dyn ProgressReporter, from: &Path, 
+        to: &Path) -> io::Result<()> {
     let mut from_file = File::open(from)?;
     let mut to_file = File::create(to)?;
     let file_size = from_file.metadata()?.len();
-    let mut buffer = [0; 8192];
+    let file_name = from.file_name().unwrap_or_default().to_string_lossy();
+    if file_size == 0 {
+        // Nothing to copy; the create above already left an empty file.
+        reporter.progress(100, &format!("Copying {} (100%)", file_name));
+        return Ok(());
+    }
+    reporter.progress(0, &format!("Copying {} (0%)", file_name));
+    let mut buffer = vec![0; *COPY_BUFFER_SIZE.lock().unwrap()];
     let mut bytes_copied = 0;
+    let mut last_progress = 0;
+    let mut last_report = Instant::now();
 
     loop {
+        if is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, 
+                    "Copy cancelled"));
+        }
         let bytes_read = from_file.read(&mut buffer)?;
         if bytes_read == 0 {
             break;
@@ -561,7 +3933,168 @@ fn copy_with_progress(bar: &nwg::ProgressBar, from: &Path, to: &Path) ->
         to_file.write_all(&buffer[..bytes_read])?;
         bytes_copied += bytes_read as u64;
         let progress = (bytes_copied * 100 / file_size) as u32;
-        update_progress(&bar, progress);
+        if progress != last_progress && (progress == 100 || 
+                last_report.elapsed() >= PROGRESS_INTERVAL) {
+            reporter.progress(progress, &format!("Copying {} ({}%)", 
+                    file_name, progress));
+            last_progress = progress;
+            last_report = Instant::now();
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{temp_dir, NullReporter, ProgressRecorder};
+
+    #[test]
+    fn finds_and_removes_a_shortcut_in_every_scope() {
+        let root = temp_dir("shortcut-scopes");
+        let user_start_menu = root.join("User Start Menu");
+        let common_start_menu = root.join("Common Start Menu");
+        for dir in [&user_start_menu, &common_start_menu] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("My App.lnk"), b"not a real link").unwrap();
+        }
+        // The per-user folder again, as another API spells it.
+        let dirs = [user_start_menu.clone(), common_start_menu.clone(), 
+                PathBuf::from(user_start_menu.to_string_lossy()
+                    .to_uppercase())];
+
+        let shortcuts = find_shortcuts_in(&dirs, "My App");
+        let paths: Vec<&PathBuf> = shortcuts.iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, [&user_start_menu.join("My App.lnk"), 
+                &common_start_menu.join("My App.lnk")]);
+
+        for (shortcut_path, _) in &shortcuts {
+            assert!(remove_shortcut(&NullReporter, shortcut_path));
+        }
+        assert!(!user_start_menu.join("My App.lnk").exists());
+        assert!(!common_start_menu.join("My App.lnk").exists());
+        assert!(find_shortcuts_in(&dirs, "My App").is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn copies_an_empty_file_with_one_full_progress_report() {
+        let dir = temp_dir("empty-copy");
+        let from = dir.join("MyApp-1.0.0.zip");
+        let to = dir.join("copy.zip");
+        fs::write(&from, b"").unwrap();
+        // Left over from an earlier copy; it must end up empty.
+        fs::write(&to, b"stale").unwrap();
+        let reporter = ProgressRecorder::default();
+
+        copy_with_progress(&reporter, &from, &to).unwrap();
+        assert_eq!(*reporter.progress.borrow(), [100]);
+        assert_eq!(fs::metadata(&to).unwrap().len(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_executable_needs_a_full_sized_mz_file() {
+        let dir = temp_dir("verify-exe");
+        let short = dir.join("short.exe");
+        let mut data = b"MZ".to_vec();
+        data.resize(MIN_EXECUTABLE_SIZE as usize - 1, 0);
+        fs::write(&short, &data).unwrap();
+        let err = verify_executable(&short).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let unsigned = dir.join("unsigned.exe");
+        fs::write(&unsigned, vec![0u8; MIN_EXECUTABLE_SIZE as usize]).unwrap();
+        let err = verify_executable(&unsigned).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let valid = dir.join("valid.exe");
+        data.push(0);
+        fs::write(&valid, &data).unwrap();
+        assert!(verify_executable(&valid).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn add_spaces_splits_words_and_numbers() {
+        assert_eq!(add_spaces("MyApp"), "My App");
+        assert_eq!(add_spaces("APIClient"), "API Client");
+        assert_eq!(add_spaces("App2Go"), "App 2 Go");
+        assert_eq!(add_spaces("MP3Player"), "MP3 Player");
+        assert_eq!(add_spaces("V2"), "V2");
+    }
+
+    #[test]
+    fn legacy_add_spaces_only_splits_after_lowercase() {
+        assert_eq!(legacy_add_spaces("MyApp"), "My App");
+        assert_eq!(legacy_add_spaces("APIClient"), "APIClient");
+        assert_eq!(legacy_add_spaces("App2Go"), "App2Go");
+        assert_eq!(legacy_add_spaces("MP3Player"), "MP3Player");
+        assert_eq!(legacy_add_spaces("V2"), "V2");
+    }
+
+    #[test]
+    fn list_zips_prefers_a_stable_subfolder() {
+        let dir = temp_dir("stable-subfolder");
+        for channel in ["beta", "stable"] {
+            fs::create_dir_all(dir.join(channel)).unwrap();
+            fs::write(dir.join(channel).join("MyApp-1.0.0.zip"), b"").unwrap();
+        }
+        let zips = list_zips(&dir).unwrap();
+        assert_eq!(zips.len(), 1);
+        assert_eq!(zips[0].0, dir.join(r"stable\MyApp-1.0.0.zip"));
+
+        // Without one, every subfolder is searched, as for one per version.
+        fs::rename(dir.join("stable"), dir.join("1.0.0")).unwrap();
+        assert_eq!(list_zips(&dir).unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn comparable_paths_ignore_case_separators_and_verbatim_prefixes() {
+        let local = comparable_path(Path::new(r"C:\Users\Me\AppData\Local"));
+        assert_eq!(local, PathBuf::from(r"c:\users\me\appdata\local"));
+        assert_eq!(comparable_path(Path::new("c:/users/me/AppData/Local")), 
+                local);
+        assert_eq!(comparable_path(
+                Path::new(r"\\?\C:\Users\Me\AppData\Local")), local);
+
+        let share = comparable_path(Path::new(r"\\Server\Share\Apps"));
+        assert_eq!(share, PathBuf::from(r"\\server\share\apps"));
+        assert_eq!(comparable_path(
+                Path::new(r"\\?\UNC\Server\Share\Apps")), share);
+    }
+
+    #[test]
+    fn shares_are_unc_and_drives_are_not() {
+        assert!(is_unc(Path::new(r"\\server\share\apps")));
+        assert!(is_unc(Path::new(r"\\?\UNC\server\share\apps")));
+        assert!(!is_unc(Path::new(r"C:\Apps")));
+        assert!(!is_unc(Path::new(r"\\?\C:\Apps")));
+        assert!(!is_unc(Path::new(r"Apps\MyApp")));
+    }
+
+    #[test]
+    fn current_user_data_replaces_a_stale_stash() {
+        let root = temp_dir("stale-stash");
+        let app_dir = root.join("MyApp");
+        let stash_dir = root.join("MyApp.userdata");
+        for (dir, text) in [(&app_dir, "new"), (&stash_dir, "old")] {
+            fs::create_dir_all(dir.join("config")).unwrap();
+            fs::write(dir.join(r"config\settings.json"), text).unwrap();
+            fs::write(dir.join("MyApp.ini"), text).unwrap();
+        }
+        let patterns = ["config/".to_string(), "*.ini".to_string()];
+
+        stash_user_data(&NullReporter, &app_dir, Path::new(""), &stash_dir, 
+                &patterns);
+        assert_eq!(fs::read_to_string(stash_dir.join(r"config\settings.json"))
+                .unwrap(), "new");
+        assert_eq!(fs::read_to_string(stash_dir.join("MyApp.ini")).unwrap(), 
+                "new");
+        assert!(!app_dir.join("config").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+// The installer's core, usable without the window: finding and copying the
+// latest zip, extracting it, shortcuts, uninstalling and version checks. 
+// Progress and questions go through a ProgressReporter, e.g.
+//
+//     app_installer::install("MyApp", &app_installer::ConsoleReporter)
+//
+// Behaviour is tuned through the statics below and those in install_utils,
+// which the command line sets.
+
+use once_cell::sync::Lazy;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub mod http_utils;
+pub mod install_utils;
+pub mod reporter;
+#[cfg(feature = "sevenz")]
+pub mod sevenz_utils;
+#[cfg(test)]
+mod test_utils;
+pub mod version_utils;
+pub mod zip_utils;
+
+pub use install_utils::{run_installation as install, InstallError};
+pub use reporter::{ConsoleReporter, LogLevel, ProgressReporter};
+
+pub static SKIP_SELF_UPDATE: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+pub static ALLOW_DOWNGRADE: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+/// Ask before an existing install is removed to make way for the new one.
+/// The window sets this unless --no-confirm is given; --silent never asks.
+pub static CONFIRM_REMOVAL: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+pub static DESKTOP_SHORTCUT: Lazy<Mutex<bool>> = Lazy::new(|| 
+        Mutex::new(false));
+pub static DRY_RUN: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
+/// The installed exe, offered to the user when the installer closes.
+pub static EXE_PATH_TO_RUN: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| 
+        Mutex::new(None));
@@ -1,38 +1,414 @@
 extern crate native_windows_gui as nwg;
 use nwg::NativeUi;
 use std::env;
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::thread;
+use chrono::Local;
+use winapi::shared::minwindef::MAX_PATH;
+use winapi::shared::windef::{HWND, RECT};
+use winapi::um::winuser::{GetClientRect, GetKeyState, GetSystemMetrics, 
+        SetWindowTextW, SM_CXVSCROLL, VK_SHIFT};
+use winapi::um::commdlg::{GetSaveFileNameW, OPENFILENAMEW, OFN_OVERWRITEPROMPT,
+        OFN_PATHMUSTEXIST};
 use once_cell::sync::Lazy;
 use std::sync::Mutex;
 
-mod zip_utils;
-mod install_utils;
-use install_utils::*;
+mod gui_reporter;
+use app_installer::*;
+use app_installer::install_utils::*;
+use app_installer::reporter::{LogFormat, LOG_FORMAT, LOG_LEVEL};
+use gui_reporter::*;
 
 pub static APP_NAME: Lazy<Mutex<Option<Box<str>>>> = Lazy::new(|| 
         Mutex::new(None));
-pub static DEBUG: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
-pub static EXE_PATH_TO_RUN: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| 
-        Mutex::new(None));
+
+const LOG_FILTER_ALL_TYPES: &str = "All types";
+const MIN_MESSAGE_WIDTH: i32 = 80;
 
 #[derive(Default)]
 pub struct FlexBoxApp {
     window: nwg::Window,
+    /// AppInstaller.ico from the installer's folder, if it has one.
+    window_icon: Option<nwg::Icon>,
     layout: nwg::FlexboxLayout,
+    app_layout: nwg::FlexboxLayout,
+    app_combo: nwg::ComboBox<String>,
+    install_button: nwg::Button,
+    browse_button: nwg::Button,
+    zip_dialog: nwg::FileDialog,
+    app_menu: nwg::Menu,
+    uninstall_item: nwg::MenuItem,
+    filter_layout: nwg::FlexboxLayout,
+    filter_input: nwg::TextInput,
+    filter_type: nwg::ComboBox<&'static str>,
     listview: nwg::ListView,
     progress_bar: nwg::ProgressBar,
+    progress_label: nwg::Label,
     layout2: nwg::FlexboxLayout,
+    run_check: nwg::CheckBox,
     button1: nwg::Button,
     button2: nwg::Button,
     button3: nwg::Button,
+    button4: nwg::Button,
+    folder_button: nwg::Button,
+    retry_button: nwg::Button,
     spacer1: nwg::Frame, 
     spacer2: nwg::Frame, 
+    /// Wakes the UI thread to apply reports queued by the install thread.
+    notice: nwg::Notice,
+    report_queue: ReportQueue,
+    install_thread: RefCell<Option<thread::JoinHandle<()>>>,
+    /// The folder of the exe just installed, for Open Folder.
+    installed_dir: RefCell<Option<PathBuf>>,
+    /// The apps of the last install (not uninstall), for Retry.
+    last_install: RefCell<Option<Vec<String>>>,
+    /// Set when no app was named on the command line, so the user picks one.
+    select_app: bool,
+    /// --launch: start with "Run after closing" ticked.
+    launch: bool,
+    install_failed: Cell<bool>,
+    close_when_finished: Cell<bool>,
 }
 
 impl FlexBoxApp {
     fn exit(&self) {
         nwg::stop_thread_dispatch();
     }
+
+    fn reporter(&self) -> ListViewReporter<'_> {
+        ListViewReporter::new(&self.listview, &self.progress_bar, 
+                &self.progress_label)
+    }
+
+    fn install_selected_app(&self) {
+        if let Some(app_name) = self.selected_app("Install", "install") {
+            *LOCAL_ZIP.lock().unwrap() = None;
+            self.start_install(vec![app_name]);
+        }
+    }
+
+    /// Installs the selected app from a zip the user picks, such as a one-off
+    /// build on a USB stick, instead of from the source.
+    fn install_selected_app_from_zip(&self) {
+        let app_name = match self.selected_app("Install", "install") {
+            Some(app_name) => app_name,
+            None => return,
+        };
+        if !self.zip_dialog.run(Some(&self.window)) {
+            return;
+        }
+        if let Ok(zip_path) = self.zip_dialog.get_selected_item() {
+            *LOCAL_ZIP.lock().unwrap() = Some(PathBuf::from(zip_path));
+            self.start_install(vec![app_name]);
+        }
+    }
+
+    fn uninstall_selected_app(&self) {
+        let app_name = match self.selected_app("Uninstall", "uninstall") {
+            Some(app_name) => app_name,
+            None => return,
+        };
+        if self.reporter().confirm("Uninstall", &format!(
+                "Remove {} and its shortcuts?", app_name)) {
+            self.start_uninstall(vec![app_name]);
+        }
+    }
+
+    fn selected_app(&self, title: &str, verb: &str) -> Option<String> {
+        let app_name = match self.app_combo.selection_string() {
+            Some(app_name) => app_name,
+            None => {
+                nwg::modal_info_message(&self.window, title, 
+                        &format!("Select an application to {}.", verb));
+                return None;
+            }
+        };
+        *APP_NAME.lock().unwrap() = Some(app_name.clone().into_boxed_str());
+        Some(app_name)
+    }
+
+    fn start_install(&self, app_names: Vec<String>) {
+        *self.last_install.borrow_mut() = Some(app_names.clone());
+        self.start_task(move |reporter| run_installations(reporter, 
+                &app_names));
+    }
+
+    fn start_uninstall(&self, app_names: Vec<String>) {
+        *self.last_install.borrow_mut() = None;
+        self.start_task(move |reporter| uninstall_all(reporter, &app_names));
+    }
+
+    /// Runs an install or uninstall on a worker thread so the window stays 
+    /// responsive and Cancel can stop it.
+    fn start_task<F>(&self, task: F) 
+            where F: FnOnce(&dyn ProgressReporter) -> Result<(), InstallError> 
+                    + Send + 'static {
+        self.app_combo.set_enabled(false);
+        self.install_button.set_enabled(false);
+        self.browse_button.set_enabled(false);
+        self.folder_button.set_enabled(false);
+        self.retry_button.set_enabled(false);
+        let reporter = ThreadReporter::new(self.report_queue.clone(), 
+                self.notice.sender());
+        let handle = thread::spawn(move || {
+            let result = task(&reporter);
+            reporter.send(ReportEvent::Finished(result.is_ok()));
+        });
+        *self.install_thread.borrow_mut() = Some(handle);
+    }
+
+    fn is_installing(&self) -> bool {
+        self.install_thread.borrow().is_some()
+    }
+
+    /// Applies everything the install thread has reported since the last 
+    /// notice.
+    fn process_reports(&self) {
+        let events: Vec<ReportEvent> = self.report_queue.lock().unwrap()
+            .drain(..).collect();
+        let reporter = self.reporter();
+        for event in events {
+            match event {
+                ReportEvent::Message { message_type, time_str, message } => 
+                        reporter.message(&message_type, &time_str, &message),
+                ReportEvent::Progress { progress, status } => 
+                        reporter.progress(progress, &status),
+                ReportEvent::Busy { status } => reporter.busy(&status),
+                ReportEvent::Confirm { title, prompt, reply } => {
+                    let _ = reply.send(reporter.confirm(&title, &prompt));
+                }
+                ReportEvent::Finished(succeeded) => 
+                        self.install_finished(succeeded),
+            }
+        }
+    }
+
+    fn install_finished(&self, succeeded: bool) {
+        if let Some(handle) = self.install_thread.borrow_mut().take() {
+            let _ = handle.join();
+        }
+        self.install_failed.set(!succeeded);
+        self.reporter().set_indeterminate(false);
+        if INSTALLER_RESTARTED.load(Ordering::SeqCst) {
+            self.exit();
+            return;
+        }
+        self.app_combo.set_enabled(true);
+        self.install_button.set_enabled(true);
+        self.browse_button.set_enabled(true);
+        self.button3.set_enabled(true);
+        self.run_check.set_enabled(EXE_PATH_TO_RUN.lock().unwrap().is_some());
+        *self.installed_dir.borrow_mut() = EXE_PATH_TO_RUN.lock().unwrap()
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf);
+        self.folder_button.set_enabled(self.installed_dir.borrow().is_some());
+        self.retry_button.set_enabled(!succeeded && 
+                self.last_install.borrow().is_some());
+        if self.close_when_finished.get() {
+            self.exit();
+        }
+    }
+
+    /// Stops a running install after its current chunk or file. With 
+    /// `then_close` the window closes once the install thread has finished.
+    fn cancel_install(&self, then_close: bool) {
+        self.close_when_finished.set(then_close);
+        if !CANCEL_REQUESTED.swap(true, Ordering::SeqCst) {
+            add_message(&self.reporter(), LogLevel::Info, "Cancelling...");
+        }
+        self.button3.set_enabled(false);
+    }
+
+    /// Installs the same apps again after a failure, e.g. once the app that
+    /// was running has been closed. The new run's messages follow the old
+    /// ones below a separator; install_app checks for running processes
+    /// again.
+    fn retry_install(&self) {
+        let app_names = match self.last_install.borrow().clone() {
+            Some(app_names) => app_names,
+            None => return,
+        };
+        add_message(&self.reporter(), LogLevel::Info, &format!(
+                "---------- Retrying {} ----------", app_names.join(", ")));
+        self.start_install(app_names);
+    }
+
+    /// Shows the installed app's folder in Explorer.
+    fn open_installed_dir(&self) {
+        let dir = match self.installed_dir.borrow().clone() {
+            Some(dir) => dir,
+            None => return,
+        };
+        if let Err(e) = std::process::Command::new("explorer.exe").arg(&dir)
+                .spawn() {
+            add_message(&self.reporter(), LogLevel::Error, 
+                    &format!("Failed to open {:?}: {}", dir, e));
+        }
+    }
+
+    fn apply_filter(&self) {
+        let message_type = self.filter_type.selection_string()
+            .filter(|selection| selection != LOG_FILTER_ALL_TYPES);
+        set_log_filter(&self.listview, &self.filter_input.text(), 
+                message_type.as_deref());
+    }
+
+    /// Gives the Message column whatever the Type and Time columns and a 
+    /// vertical scrollbar leave, but never less than MIN_MESSAGE_WIDTH.
+    fn resize_message_column(&self) {
+        let hwnd = match self.listview.handle.hwnd() {
+            Some(hwnd) => hwnd,
+            None => return,
+        };
+        let mut rect: RECT = unsafe { std::mem::zeroed() };
+        if unsafe { GetClientRect(hwnd, &mut rect) } == 0 {
+            return;
+        }
+        let fixed_width: i32 = (0..2)
+            .filter_map(|i| self.listview.column(i, 16))
+            .map(|column| column.width)
+            .sum();
+        let scrollbar_width = unsafe { GetSystemMetrics(SM_CXVSCROLL) };
+        let width = (rect.right - rect.left) - fixed_width - scrollbar_width;
+        self.listview.set_column_width(2, 
+                width.max(MIN_MESSAGE_WIDTH) as isize);
+    }
+
+    /// The lines that start a copied or saved log, naming the installer's 
+    /// version and the app for a support ticket. `action` says what was 
+    /// done with it and when.
+    fn log_header(&self, action: &str) -> String {
+        let app_name = APP_NAME.lock().unwrap().clone().unwrap_or_default();
+        format!("AppInstaller {} log for {}\r\n{} {}\r\n", 
+                env!("CARGO_PKG_VERSION"), app_name, action, 
+                Local::now().format("%Y-%m-%d %H:%M:%S"))
+    }
+
+    /// Copies every row to the clipboard, hidden ones included, or with 
+    /// Shift held only the rows the filter shows.
+    fn copy_log(&self) {
+        let filtered = unsafe { GetKeyState(VK_SHIFT) } < 0;
+        let mut text = self.log_header("Copied");
+        if filtered {
+            text.push_str("Only the rows matching the filter\r\n");
+        }
+        text.push_str("\r\n");
+        text.push_str(&log_text(filtered));
+        nwg::Clipboard::set_data_text(&self.window.handle, &text);
+    }
+
+    fn save_log(&self) {
+        let app_name = APP_NAME.lock().unwrap().clone().unwrap_or_default();
+        let now = Local::now();
+        let default_name = format!("{}-install-{}.log", app_name, 
+                now.format("%Y-%m-%d"));
+        let path = match save_file_dialog(self.window.handle.hwnd(), 
+                &default_name) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mut text = self.log_header("Saved");
+        text.push_str("\r\n");
+        text.push_str(&log_text(false));
+        match fs::write(&path, text) {
+            Ok(_) => add_message(&self.reporter(), LogLevel::Info, 
+                    &format!("Log saved to {:?}", path)),
+            Err(e) => {
+                nwg::modal_error_message(&self.window, "Save Log", 
+                        &format!("Failed to save log to {:?}: {}", path, e));
+            }
+        }
+    }
+}
+
+/// Uninstalls each app, carrying on past failures. Fails if any did.
+fn uninstall_all(reporter: &dyn ProgressReporter, app_names: &[String]) 
+        -> Result<(), InstallError> {
+    let mut result = Ok(());
+    for app_name in app_names {
+        if let Err(e) = run_uninstall(reporter, app_name) {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+/// Reads the app names in a --batch file: one per line, skipping blank 
+/// lines and lines starting with #.
+fn read_batch_file(path: &str) -> std::io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// A --source, --install-root or --zip value made absolute, so the restarted 
+/// installer and the Apps & features entry see the same folder. UNC paths
+/// and URLs are kept as given.
+fn absolute_path(path: &str) -> PathBuf {
+    if app_installer::http_utils::is_url(path) {
+        return PathBuf::from(path);
+    }
+    std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// The window icon, `AppInstaller.ico` next to the installer's exe. The 
+/// window keeps the default icon when there is none.
+fn load_window_icon() -> Option<nwg::Icon> {
+    let icon_path = std::env::current_exe().ok()?
+        .with_file_name("AppInstaller.ico");
+    let mut icon = nwg::Icon::default();
+    nwg::Icon::builder()
+        .source_file(Some(icon_path.to_str()?))
+        .build(&mut icon)
+        .ok()?;
+    Some(icon)
+}
+
+/// Sets a control's window text, which screen readers use as its name when
+/// no label precedes it.
+fn set_accessible_name(handle: &nwg::ControlHandle, name: &str) {
+    if let Some(hwnd) = handle.hwnd() {
+        let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        unsafe { SetWindowTextW(hwnd, name.as_ptr()) };
+    }
+}
+
+/// Shows the common Save As dialog with `default_name` pre-filled. The nwg 
+/// FileDialog has no way to suggest a file name, hence the raw call.
+fn save_file_dialog(owner: Option<HWND>, default_name: &str) 
+        -> Option<PathBuf> {
+    let mut file_buf = [0u16; MAX_PATH];
+    for (i, c) in default_name.encode_utf16().take(MAX_PATH - 1).enumerate() {
+        file_buf[i] = c;
+    }
+    let filter: Vec<u16> = "Log files (*.log)\0*.log\0All files (*.*)\0*.*\0\0"
+            .encode_utf16().collect();
+    let default_ext: Vec<u16> = "log\0".encode_utf16().collect();
+
+    let mut ofn: OPENFILENAMEW = unsafe { std::mem::zeroed() };
+    ofn.lStructSize = std::mem::size_of::<OPENFILENAMEW>() as u32;
+    ofn.hwndOwner = owner.unwrap_or(std::ptr::null_mut());
+    ofn.lpstrFilter = filter.as_ptr();
+    ofn.lpstrFile = file_buf.as_mut_ptr();
+    ofn.nMaxFile = file_buf.len() as u32;
+    ofn.lpstrDefExt = default_ext.as_ptr();
+    ofn.Flags = OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST;
+
+    if unsafe { GetSaveFileNameW(&mut ofn) } == 0 {
+        return None;
+    }
+    let len = file_buf.iter().position(|&c| c == 0).unwrap_or(file_buf.len());
+    Some(PathBuf::from(OsString::from_wide(&file_buf[..len])))
 }
 
 mod flexbox_app_ui {
@@ -53,16 +429,81 @@ mod flexbox_app_ui {
                 nwg::NwgError> {
             use nwg::Event as E;
             
-            // Controls
+            // Controls. They are created in tab order. The window title, 
+            // which screen readers announce, names the app when there is 
+            // one.
+            let title = match APP_NAME.lock().unwrap().as_deref() {
+                Some(app_name) => format!("App Installer - {}", app_name),
+                None => "App Installer".to_string(),
+            };
+            data.window_icon = load_window_icon();
             nwg::Window::builder()
                 .size((700, 500))
                 .position((300, 300))
-                .title("App Installer")
+                .title(&title)
+                .icon(data.window_icon.as_ref())
                 .build(&mut data.window)?;
 
+            if data.select_app {
+                let apps = match list_available_apps() {
+                    Ok(apps) => apps,
+                    Err(e) => {
+                        nwg::modal_error_message(&data.window, "Install", 
+                                &format!("Could not list applications in \
+                                {:?}: {}", REMOTE_DIR.lock().unwrap(), e));
+                        Vec::new()
+                    }
+                };
+                nwg::ComboBox::builder()
+                    .parent(&data.window)
+                    .collection(apps)
+                    .build(&mut data.app_combo)?;
+
+                nwg::Button::builder()
+                    .text("&Install")
+                    .parent(&data.window)
+                    .build(&mut data.install_button)?;
+
+                nwg::Button::builder()
+                    .text("&Browse...")
+                    .parent(&data.window)
+                    .build(&mut data.browse_button)?;
+
+                nwg::FileDialog::builder()
+                    .title("Install from Zip")
+                    .action(nwg::FileDialogAction::Open)
+                    .filters("Zip files(*.zip)|All files(*.*)")
+                    .build(&mut data.zip_dialog)?;
+
+                nwg::Menu::builder()
+                    .popup(true)
+                    .parent(&data.window)
+                    .build(&mut data.app_menu)?;
+
+                nwg::MenuItem::builder()
+                    .text("&Uninstall")
+                    .parent(&data.app_menu)
+                    .build(&mut data.uninstall_item)?;
+            }
+
+            nwg::Notice::builder()
+                .parent(&data.window)
+                .build(&mut data.notice)?;
+
+            nwg::TextInput::builder()
+                .parent(&data.window)
+                .placeholder_text(Some("Filter messages"))
+                .build(&mut data.filter_input)?;
+
+            nwg::ComboBox::builder()
+                .parent(&data.window)
+                .collection(vec![LOG_FILTER_ALL_TYPES, "ERROR", "WARN", 
+                        "INFO", "DEBUG", "TRACE"])
+                .selected_index(Some(0))
+                .build(&mut data.filter_type)?;
+
             nwg::ListView::builder()
                 .parent(&data.window)
-                .focus(true)
                 .list_style(nwg::ListViewStyle::Detailed)
                 .ex_flags(nwg::ListViewExFlags::GRID | 
                         nwg::ListViewExFlags::FULL_ROW_SELECT)
@@ -73,24 +514,64 @@ mod flexbox_app_ui {
                 .parent(&data.window)
                 .build(&mut data.progress_bar)?;
 
+            nwg::Label::builder()
+                .text("")
+                .parent(&data.window)
+                .build(&mut data.progress_label)?;
+
+            // Enabled once there is an installed app to run.
+            nwg::CheckBox::builder()
+                .text("&Run after closing")
+                .parent(&data.window)
+                .enabled(false)
+                .check_state(if data.launch {
+                    nwg::CheckBoxState::Checked
+                } else {
+                    nwg::CheckBoxState::Unchecked
+                })
+                .build(&mut data.run_check)?;
+
+            // Close is the default button; with no app named, the app list
+            // starts with the focus instead.
             nwg::Button::builder()
-                .text("Close")
+                .text("&Close")
                 .parent(&data.window)
-                .focus(true)
+                .focus(!data.select_app)
                 .build(&mut data.button1)?;
 
             nwg::Button::builder()
-                .text("Copy to Clipboard")
+                .text("Co&py to Clipboard")
                 .parent(&data.window)
-                .focus(true)
                 .build(&mut data.button2)?;
 
             nwg::Button::builder()
-                .text("Cancel")
+                .text("&Save Log...")
+                .parent(&data.window)
+                .build(&mut data.button4)?;
+
+            // Enabled once an install has succeeded.
+            nwg::Button::builder()
+                .text("Open &Folder")
+                .parent(&data.window)
+                .enabled(false)
+                .build(&mut data.folder_button)?;
+
+            // Enabled once an install has failed.
+            nwg::Button::builder()
+                .text("R&etry")
+                .parent(&data.window)
+                .enabled(false)
+                .build(&mut data.retry_button)?;
+
+            nwg::Button::builder()
+                .text("C&ancel")
                 .parent(&data.window)
-                .focus(true)
                 .build(&mut data.button3)?;
 
+            if data.select_app {
+                data.app_combo.set_focus();
+            }
+
             nwg::Frame::builder()
                 .parent(&data.window)
                 .flags(nwg::FrameFlags::VISIBLE)
@@ -109,53 +590,108 @@ mod flexbox_app_ui {
 
             // Events
             let evt_ui = Rc::downgrade(&ui.inner);
-            let handle_events = move |evt, _evt_data, handle| {
+            let handle_events = move |evt, evt_data, handle| {
                 if let Some(evt_ui) = evt_ui.upgrade() {
                     match evt {
                         E::OnWindowClose => {
                             if &handle == &evt_ui.window {
-                                FlexBoxApp::exit(&evt_ui);
+                                if evt_ui.is_installing() {
+                                    if let nwg::EventData::OnWindowClose(
+                                            data) = &evt_data {
+                                        data.close(false);
+                                    }
+                                    evt_ui.cancel_install(true);
+                                } else {
+                                    FlexBoxApp::exit(&evt_ui);
+                                }
+                            }
+                        },
+                        E::OnNotice => {
+                            if &handle == &evt_ui.notice {
+                                evt_ui.process_reports();
                             }
                         },
                         E::OnButtonClick => {
-                            if &handle == &evt_ui.button1 {
-                                if let Some(path) = 
-                                        EXE_PATH_TO_RUN.lock().unwrap().take() {
+                            if evt_ui.is_installing() && (&handle == 
+                                    &evt_ui.button1 || 
+                                    &handle == &evt_ui.button3) {
+                                evt_ui.cancel_install(
+                                        &handle == &evt_ui.button1);
+                            } else if &handle == &evt_ui.button1 {
+                                let run = evt_ui.run_check.check_state() == 
+                                        nwg::CheckBoxState::Checked;
+                                if let Some(path) = EXE_PATH_TO_RUN.lock()
+                                        .unwrap().take().filter(|_| run) {
                                     Command::new(path)
                                         .spawn()
                                         .expect("Failed to run executable");
                                 }
                                 FlexBoxApp::exit(&evt_ui);
                             } else if &handle == &evt_ui.button2 {
-                                let mut text = String::new();
-                                for i in 0..evt_ui.listview.len() {
-                                    if let Some(item) = evt_ui.listview.item(
-                                            i as usize, 0, 256) {
-                                        text.push_str(&item.text);
-                                        text.push_str("\t");
-                                    }
-                                    if let Some(item) = evt_ui.listview.item(
-                                            i as usize, 1, 256) {
-                                        text.push_str(&item.text);
-                                        text.push_str("\t");
-                                    }
-                                    if let Some(item) = evt_ui.listview.item(
-                                            i as usize, 2, 1024) {
-                                        text.push_str(&item.text);
-                                        text.push_str("\r\n");
-                                    }
-                                }
-                                nwg::Clipboard::set_data_text(
-                                        &evt_ui.window.handle, &text);
+                                evt_ui.copy_log();
+                            } else if &handle == &evt_ui.button4 {
+                                evt_ui.save_log();
+                            } else if &handle == &evt_ui.folder_button {
+                                evt_ui.open_installed_dir();
+                            } else if &handle == &evt_ui.retry_button {
+                                evt_ui.retry_install();
                             } else if &handle == &evt_ui.button3 {
                                 FlexBoxApp::exit(&evt_ui);
+                            } else if &handle == &evt_ui.install_button {
+                                evt_ui.install_selected_app();
+                            } else if &handle == &evt_ui.browse_button {
+                                evt_ui.install_selected_app_from_zip();
+                            }
+                        },
+                        // Enter and Esc reach the window as IDOK and 
+                        // IDCANCEL when no button has the focus.
+                        E::OnKeyEnter => {
+                            if evt_ui.select_app && evt_ui.app_combo.focus() {
+                                if !evt_ui.is_installing() {
+                                    evt_ui.install_selected_app();
+                                }
+                            } else if !evt_ui.filter_input.focus() {
+                                evt_ui.button1.click();
+                            }
+                        },
+                        E::OnKeyEsc => {
+                            if evt_ui.button3.enabled() {
+                                evt_ui.button3.click();
+                            }
+                        },
+                        E::OnContextMenu => {
+                            if evt_ui.select_app && 
+                                    &handle == &evt_ui.app_combo && 
+                                    !evt_ui.is_installing() {
+                                let (x, y) = nwg::GlobalCursor::position();
+                                evt_ui.app_menu.popup(x, y);
+                            }
+                        },
+                        E::OnMenuItemSelected => {
+                            if &handle == &evt_ui.uninstall_item {
+                                evt_ui.uninstall_selected_app();
+                            }
+                        },
+                        E::OnTextInput => {
+                            if &handle == &evt_ui.filter_input {
+                                evt_ui.apply_filter();
+                            }
+                        },
+                        E::OnComboxBoxSelection => {
+                            if &handle == &evt_ui.filter_type {
+                                evt_ui.apply_filter();
+                            }
+                        },
+                        E::OnListViewColumnClick => {
+                            if &handle == &evt_ui.listview {
+                                let (_, column) = 
+                                        evt_data.on_list_view_item_index();
+                                sort_log(&evt_ui.listview, column);
                             }
                         },
                         E::OnResize => {
                             if &handle == &evt_ui.window {
-                                let (w, _) = evt_ui.listview.size();
-                                evt_ui.listview.set_column_width(2, 
-                                        (w - 200) as isize);
+                                evt_ui.resize_message_column();
                             }
                         },
                         _ => {}
@@ -167,61 +703,159 @@ mod flexbox_app_ui {
                     nwg::full_bind_event_handler(&ui.window.handle, 
                             handle_events));
 
-            ui.listview.insert_column("TYPE");
+            // Clicking a header sorts by that column.
+            ui.listview.insert_column("Type");
             ui.listview.set_column_width(0, 100);
-            ui.listview.set_column_sort_arrow(0, 
-                    Some(nwg::ListViewColumnSortArrow::Down));
 
-            ui.listview.insert_column("TIME");
-            ui.listview.set_column_width(1, 100);
-            ui.listview.set_column_sort_arrow(1, 
-                    Some(nwg::ListViewColumnSortArrow::Down));
+            ui.listview.insert_column("Time");
+            ui.listview.set_column_width(1, 170);
 
-            ui.listview.insert_column("MESSAGE");
-            // Set this to window width - 20
-            ui.listview.set_column_width(2, 480);
-            ui.listview.set_column_sort_arrow(2, 
-                    Some(nwg::ListViewColumnSortArrow::Down));
+            ui.listview.insert_column("Message");
+            ui.listview.set_column_width(2, 410);
+            show_sort_arrow(&ui.listview);
 
             ui.listview.set_headers_enabled(true);
 
+            // Screen readers announce these controls by their window text.
+            set_accessible_name(&ui.listview.handle, "Installation log");
+            set_accessible_name(&ui.progress_bar.handle, "Progress");
+
             // Layout
-            use nwg::stretch::{geometry::Size, style::{Dimension as D, 
+            use nwg::stretch::{geometry::{Rect, Size}, style::{Dimension as D, 
                     FlexDirection}};
 
+            // The buttons keep a fixed preferred width (shrinking only down to
+            // a minimum on narrow windows) and the spacers share any extra 
+            // width equally, so the buttons stay centred when maximized.
+            let button_margin = Rect { start: D::Points(5.0), 
+                    end: D::Points(5.0), top: D::Undefined, 
+                    bottom: D::Undefined };
+
             nwg::FlexboxLayout::builder()
                 .parent(&ui.window)
                 .flex_direction(FlexDirection::Row)
                 .child(&ui.spacer1)
-                    .child_flex_grow(2.0)
-                    .child_size(Size { width: D::Points(200.0), 
+                    .child_flex_grow(1.0)
+                    .child_flex_basis(D::Points(0.0))
+                    .child_size(Size { width: D::Auto, 
                             height: D::Points(20.0) })
+                .child(&ui.run_check)
+                    .child_margin(button_margin)
+                    .child_size(Size { width: D::Points(150.0), 
+                            height: D::Points(40.0) })
+                    .child_min_size(Size { width: D::Points(130.0), 
+                            height: D::Points(40.0) })
                 .child(&ui.button1)
-                    .child_size(Size { width: D::Points(200.0), 
+                    .child_margin(button_margin)
+                    .child_size(Size { width: D::Points(160.0), 
+                            height: D::Points(40.0) })
+                    .child_min_size(Size { width: D::Points(100.0), 
                             height: D::Points(40.0) })
                 .child(&ui.button2)
-                    .child_size(Size { width: D::Points(310.0), 
+                    .child_margin(button_margin)
+                    .child_size(Size { width: D::Points(200.0), 
+                            height: D::Points(40.0) })
+                    .child_min_size(Size { width: D::Points(140.0), 
+                            height: D::Points(40.0) })
+                .child(&ui.button4)
+                    .child_margin(button_margin)
+                    .child_size(Size { width: D::Points(160.0), 
+                            height: D::Points(40.0) })
+                    .child_min_size(Size { width: D::Points(100.0), 
+                            height: D::Points(40.0) })
+                .child(&ui.folder_button)
+                    .child_margin(button_margin)
+                    .child_size(Size { width: D::Points(160.0), 
+                            height: D::Points(40.0) })
+                    .child_min_size(Size { width: D::Points(100.0), 
+                            height: D::Points(40.0) })
+                .child(&ui.retry_button)
+                    .child_margin(button_margin)
+                    .child_size(Size { width: D::Points(160.0), 
+                            height: D::Points(40.0) })
+                    .child_min_size(Size { width: D::Points(100.0), 
                             height: D::Points(40.0) })
                 .child(&ui.button3)
-                    .child_size(Size { width: D::Points(200.0), 
+                    .child_margin(button_margin)
+                    .child_size(Size { width: D::Points(160.0), 
+                            height: D::Points(40.0) })
+                    .child_min_size(Size { width: D::Points(100.0), 
                             height: D::Points(40.0) })
                 .child(&ui.spacer2)
-                    .child_flex_grow(2.0)
-                    .child_size(Size { width: D::Points(200.0), 
+                    .child_flex_grow(1.0)
+                    .child_flex_basis(D::Points(0.0))
+                    .child_size(Size { width: D::Auto, 
                             height: D::Points(20.0) })
                 .build_partial(&ui.layout2)?;
 
             nwg::FlexboxLayout::builder()
                 .parent(&ui.window)
-                .flex_direction(FlexDirection::Column)
+                .flex_direction(FlexDirection::Row)
+                .padding(Rect { start: D::Points(0.0), end: D::Points(0.0), 
+                        top: D::Points(0.0), bottom: D::Points(0.0) })
+                .child(&ui.filter_input)
+                    .child_flex_grow(1.0)
+                    .child_size(Size { width: D::Auto, 
+                            height: D::Points(25.0) })
+                .child(&ui.filter_type)
+                    .child_margin(Rect { start: D::Points(5.0), 
+                            end: D::Undefined, top: D::Undefined, 
+                            bottom: D::Undefined })
+                    .child_size(Size { width: D::Points(120.0), 
+                            height: D::Points(25.0) })
+                .build_partial(&ui.filter_layout)?;
+
+            let mut main_layout = nwg::FlexboxLayout::builder()
+                .parent(&ui.window)
+                .flex_direction(FlexDirection::Column);
+            if ui.select_app {
+                nwg::FlexboxLayout::builder()
+                    .parent(&ui.window)
+                    .flex_direction(FlexDirection::Row)
+                    .padding(Rect { start: D::Points(0.0), 
+                            end: D::Points(0.0), top: D::Points(0.0), 
+                            bottom: D::Points(0.0) })
+                    .child(&ui.app_combo)
+                        .child_flex_grow(1.0)
+                        .child_size(Size { width: D::Auto, 
+                                height: D::Points(25.0) })
+                    .child(&ui.install_button)
+                        .child_margin(Rect { start: D::Points(5.0), 
+                                end: D::Undefined, top: D::Undefined, 
+                                bottom: D::Undefined })
+                        .child_size(Size { width: D::Points(120.0), 
+                                height: D::Points(25.0) })
+                    .child(&ui.browse_button)
+                        .child_margin(Rect { start: D::Points(5.0), 
+                                end: D::Undefined, top: D::Undefined, 
+                                bottom: D::Undefined })
+                        .child_size(Size { width: D::Points(120.0), 
+                                height: D::Points(25.0) })
+                    .build_partial(&ui.app_layout)?;
+                main_layout = main_layout
+                    .child_layout(&ui.app_layout)
+                        .child_flex_shrink(0.0)
+                        .child_size(Size { width: D::Auto, 
+                                height: D::Points(25.0) });
+            }
+            main_layout
+                .child_layout(&ui.filter_layout)
+                    .child_flex_shrink(0.0)
+                    .child_size(Size { width: D::Auto, height: D::Points(25.0) })
                 .child(&ui.listview)
                     .child_flex_grow(2.0)
                     .child_size(Size { width: D::Auto, height: D::Auto })
+                .child(&ui.progress_label)
+                    .child_flex_shrink(0.0)
+                    .child_size(Size{width: D::Auto, height: D::Points(20.0) })
                 .child(&ui.progress_bar)
+                    .child_flex_shrink(0.0)
                     .child_size(Size{width: D::Auto, height: D::Points(20.0) })
                 .child_layout(&ui.layout2)
-                    .child_size(Size { width: D::Auto, height: D::Auto })
+                    .child_flex_shrink(0.0)
+                    .child_size(Size { width: D::Auto, height: D::Points(50.0) })
                 .build(&ui.layout)?;
+            ui.resize_message_column();
             
             return Ok(ui);
         }
@@ -248,31 +882,334 @@ mod flexbox_app_ui {
 }
 
 fn main() {
-    nwg::init().expect("Failed to init Native Windows GUI");
-    nwg::Font::set_global_family("Segoe UI").expect(
-            "Failed to set default font");
-    let args: Vec<String> = env::args().skip(1).collect();
-    let mut app_name: String = "AppInstaller".to_string();
-    let mut debug_mode = false;
+    let mut args = env::args().skip(1);
+    let mut app_names: Vec<String> = Vec::new();
+    let mut log_level = LogLevel::Info;
+    let mut skip_self_update = false;
+    let mut silent = false;
+    let mut launch = false;
+    let mut no_confirm = false;
+    let mut check_target: Option<String> = None;
+    let mut list = false;
+    let mut update_check_app: Option<String> = None;
+    let mut uninstall = false;
 
-    for arg in args {
+    while let Some(arg) = args.next() {
         if arg == "--debug" {
-            debug_mode = true;
+            log_level = LogLevel::Debug;
+        } else if arg == "--silent" {
+            silent = true;
+        } else if arg == "--launch" {
+            launch = true;
+        } else if arg == "--no-self-update" {
+            skip_self_update = true;
+        } else if arg == "--no-confirm" {
+            no_confirm = true;
+        } else if arg == "--allow-downgrade" {
+            *ALLOW_DOWNGRADE.lock().unwrap() = true;
+        } else if arg == "--dry-run" {
+            *DRY_RUN.lock().unwrap() = true;
+        } else if arg == "--allow-duplicate-entries" {
+            *ALLOW_DUPLICATE_ENTRIES.lock().unwrap() = true;
+        } else if arg == "--verify-checksum" {
+            *VERIFY_CHECKSUM.lock().unwrap() = true;
+        } else if arg == "--all-users" {
+            *ALL_USERS.lock().unwrap() = true;
+        } else if arg == "--register-uninstall" {
+            *REGISTER_UNINSTALL.lock().unwrap() = true;
+        } else if arg == "--desktop-shortcut" {
+            *DESKTOP_SHORTCUT.lock().unwrap() = true;
+        } else if arg == "--shortcut-args" {
+            match args.next() {
+                Some(arguments) => *SHORTCUT_ARGS.lock().unwrap() = 
+                        Some(arguments),
+                None => {
+                    eprintln!("Error: --shortcut-args requires a value.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--shortcut-workdir" {
+            match args.next() {
+                Some(dir) => *SHORTCUT_WORKDIR.lock().unwrap() = 
+                        Some(PathBuf::from(dir)),
+                None => {
+                    eprintln!("Error: --shortcut-workdir requires a path.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--retries" {
+            match args.next().and_then(|v| v.parse::<u32>().ok()) {
+                Some(retries) => *COPY_RETRIES.lock().unwrap() = retries,
+                None => {
+                    eprintln!("Error: --retries requires a number.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--timeout" {
+            match args.next().and_then(|v| v.parse::<u64>().ok())
+                    .filter(|seconds| *seconds > 0) {
+                Some(seconds) => *INSTALL_TIMEOUT.lock().unwrap() = 
+                        Some(std::time::Duration::from_secs(seconds)),
+                None => {
+                    eprintln!("Error: --timeout requires a number of seconds.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--copy-buffer" {
+            match args.next().and_then(|v| v.parse::<usize>().ok())
+                    .filter(|kb| (1..=64 * 1024).contains(kb)) {
+                Some(kb) => *COPY_BUFFER_SIZE.lock().unwrap() = kb * 1024,
+                None => {
+                    eprintln!("Error: --copy-buffer requires a size in KB \
+                            from 1 to 65536.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--max-log-rows" {
+            match args.next().and_then(|v| v.parse::<usize>().ok()) {
+                Some(rows) => *MAX_LOG_ROWS.lock().unwrap() = rows,
+                None => {
+                    eprintln!("Error: --max-log-rows requires a number.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--source" {
+            match args.next() {
+                Some(source) => *REMOTE_DIR.lock().unwrap() = 
+                        absolute_path(&source),
+                None => {
+                    eprintln!("Error: --source requires a path.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--zip" {
+            match args.next() {
+                Some(zip) => *LOCAL_ZIP.lock().unwrap() = 
+                        Some(absolute_path(&zip)),
+                None => {
+                    eprintln!("Error: --zip requires a path.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--stdin" {
+            *ZIP_FROM_STDIN.lock().unwrap() = true;
+        } else if arg == "--channel" {
+            match args.next() {
+                Some(channel) => *CHANNEL.lock().unwrap() = Some(channel),
+                None => {
+                    eprintln!("Error: --channel requires a name.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--staging-dir" {
+            // Kept as given: a relative path is taken from LOCALAPPDATA, not
+            // the current folder.
+            match args.next() {
+                Some(dir) => *STAGING_DIR.lock().unwrap() = 
+                        Some(PathBuf::from(dir)),
+                None => {
+                    eprintln!("Error: --staging-dir requires a folder.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--install-root" {
+            match args.next() {
+                Some(root) => *INSTALL_ROOT.lock().unwrap() = 
+                        Some(absolute_path(&root)),
+                None => {
+                    eprintln!("Error: --install-root requires a path.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--newest-by" {
+            let newest_by = match args.next().as_deref() {
+                Some("version") => NewestBy::Version,
+                Some("mtime") => NewestBy::Mtime,
+                _ => {
+                    eprintln!("Error: --newest-by must be version or mtime.");
+                    std::process::exit(1);
+                }
+            };
+            *NEWEST_BY.lock().unwrap() = newest_by;
+        } else if arg == "--version" {
+            let version = args.next().as_deref()
+                .and_then(version_utils::parse_semver);
+            match version {
+                Some(_) => *PINNED_VERSION.lock().unwrap() = version,
+                None => {
+                    eprintln!("Error: --version requires a version such as \
+                            1.2.3.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--log-format" {
+            let log_format = match args.next().as_deref() {
+                Some("text") => LogFormat::Text,
+                Some("json") => LogFormat::Json,
+                _ => {
+                    eprintln!("Error: --log-format must be text or json.");
+                    std::process::exit(1);
+                }
+            };
+            *LOG_FORMAT.lock().unwrap() = log_format;
+        } else if arg == "--log-level" {
+            match args.next().as_deref().and_then(LogLevel::parse) {
+                Some(level) => log_level = level,
+                None => {
+                    eprintln!("Error: --log-level must be error, warn, info, \
+                            debug or trace.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--uninstall" {
+            match args.next() {
+                Some(name) => {
+                    app_names.push(name);
+                    uninstall = true;
+                }
+                None => {
+                    eprintln!("Error: --uninstall requires an app name.");
+                    std::process::exit(1);
+                }
+            }
+        } else if arg == "--list" {
+            list = true;
+        } else if arg == "--check" {
+            check_target = args.next();
+            if check_target.is_none() {
+                eprintln!("Error: --check requires a zip path or app name.");
+                std::process::exit(1);
+            }
+        } else if arg == "--check-update" {
+            update_check_app = args.next();
+            if update_check_app.is_none() {
+                eprintln!("Error: --check-update requires an app name.");
+                std::process::exit(1);
+            }
+        } else if arg == "--batch" {
+            let path = match args.next() {
+                Some(path) => path,
+                None => {
+                    eprintln!("Error: --batch requires a file.");
+                    std::process::exit(1);
+                }
+            };
+            match read_batch_file(&path) {
+                Ok(names) => app_names.extend(names),
+                Err(e) => {
+                    eprintln!("Error: Could not read {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
         } else {
-            app_name = arg;
+            app_names.push(arg);
         }
     }
 
-    if app_name == "AppInstaller" {
+    if let Some(target) = check_target {
+        std::process::exit(if check_zip(&target) { 0 } else { 1 });
+    }
+
+    if let Some(app_name) = update_check_app {
+        std::process::exit(check_for_update(&app_name));
+    }
+    if list && silent {
+        std::process::exit(if print_app_list() { 0 } else { 1 });
+    }
+
+    // Without an app name the window lets the user pick one; --list shows
+    // the apps in the window, which can then install one.
+    let select_app = app_names.is_empty() || list;
+    if list {
+        app_names.clear();
+        uninstall = false;
+        *LOCAL_ZIP.lock().unwrap() = None;
+    }
+    if select_app && silent {
         eprintln!("Error: No application name argument provided.");
         std::process::exit(1);
     }
+    // The zip is installed as the named app, so there must be exactly one.
+    if LOCAL_ZIP.lock().unwrap().is_some() && 
+            (app_names.len() != 1 || uninstall) {
+        eprintln!("Error: --zip requires exactly one app name to install.");
+        std::process::exit(1);
+    }
+    if *ZIP_FROM_STDIN.lock().unwrap() {
+        if app_names.len() != 1 || uninstall {
+            eprintln!("Error: --stdin requires exactly one app name to \
+                    install.");
+            std::process::exit(1);
+        }
+        if LOCAL_ZIP.lock().unwrap().is_some() {
+            eprintln!("Error: --stdin and --zip can't be used together.");
+            std::process::exit(1);
+        }
+    }
+
+    if let [app_name] = app_names.as_slice() {
+        *APP_NAME.lock().unwrap() = Some(app_name.clone().into_boxed_str());
+    } else if !select_app {
+        *APP_NAME.lock().unwrap() = Some("apps".into());
+    }
+    *LOG_LEVEL.lock().unwrap() = log_level;
+    *SKIP_SELF_UPDATE.lock().unwrap() = skip_self_update;
+    *CONFIRM_REMOVAL.lock().unwrap() = !silent && !no_confirm;
+
+    // Nothing can be installed or logged without LOCALAPPDATA, so say so 
+    // once and stop rather than failing at every step.
+    if get_local_appdata_root().is_none() {
+        let message = "Could not find the LOCALAPPDATA folder, where apps \
+                and the installer's log are kept.";
+        if silent {
+            eprintln!("Error: {}", message);
+        } else {
+            nwg::init().expect("Failed to init Native Windows GUI");
+            nwg::error_message("App Installer", message);
+        }
+        std::process::exit(1);
+    }
 
-    *APP_NAME.lock().unwrap() = Some(app_name.clone().into_boxed_str());
-    *DEBUG.lock().unwrap() = debug_mode;
-    let ui = FlexBoxApp::build_ui(Default::default()).expect(
-            "Failed to build UI");
-    run_installation(&ui.listview, &ui.progress_bar, &app_name);
+    if silent {
+        let result = if uninstall {
+            uninstall_all(&ConsoleReporter, &app_names)
+        } else {
+            run_installations(&ConsoleReporter, &app_names)
+        };
+        if result.is_err() {
+            std::process::exit(1);
+        }
+        if let Some(path) = EXE_PATH_TO_RUN.lock().unwrap().take()
+                .filter(|_| launch) {
+            match std::process::Command::new(&path).spawn() {
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Error: Failed to run {:?}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if !REBOOT_REQUIRED.lock().unwrap().is_empty() {
+            std::process::exit(EXIT_REBOOT_REQUIRED);
+        }
+        std::process::exit(0);
+    }
+
+    nwg::init().expect("Failed to init Native Windows GUI");
+    nwg::Font::set_global_family("Segoe UI").expect(
+            "Failed to set default font");
+    let ui = FlexBoxApp::build_ui(FlexBoxApp { select_app, launch, 
+            ..Default::default() }).expect("Failed to build UI");
+    if list {
+        report_app_list(&ui.reporter());
+    } else if uninstall {
+        ui.start_uninstall(app_names);
+    } else if !select_app {
+        ui.start_install(app_names);
+    }
     nwg::dispatch_thread_events();
+    if ui.install_failed.get() {
+        std::process::exit(1);
+    }
 }
 
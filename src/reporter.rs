@@ -0,0 +1,105 @@
+// Where installation messages and progress go. The window implements this
+// over its listview and progress bar; --silent uses the console.
+
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+/// How add_message writes rows to stdout besides the reporter.
+pub static LOG_FORMAT: Lazy<Mutex<LogFormat>> = Lazy::new(|| 
+        Mutex::new(LogFormat::Text));
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Only the reporter shows the row.
+    Text,
+    /// Also one `{"level":..,"ts":..,"msg":..}` object per line on stdout, 
+    /// with a full ISO-8601 timestamp, for log aggregators.
+    Json,
+}
+
+/// The most detailed rows add_message passes on; the log file gets them all.
+pub static LOG_LEVEL: Lazy<Mutex<LogLevel>> = Lazy::new(|| 
+        Mutex::new(LogLevel::Info));
+
+/// How serious a row is, most serious first, so a row is shown when its 
+/// level is at most LOG_LEVEL.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    /// The install (or part of it) failed.
+    Error,
+    /// Something went wrong but the install carries on.
+    Warn,
+    Info,
+    Debug,
+    /// Per-file detail, such as each file extracted.
+    Trace,
+}
+
+impl LogLevel {
+    /// The name shown in the log's type column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        }
+    }
+
+    /// Parses a --log-level value such as "warn", ignoring case.
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        match name.to_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+pub trait ProgressReporter {
+    /// Shows one log row. --log-level filtering has already been applied.
+    fn message(&self, message_type: &str, time_str: &str, message: &str);
+    /// Reports progress of the current copy or extraction as a percentage,
+    /// with `status` saying what is being done, e.g. "Extracting file 12 of
+    /// 48 (25%)". Each phase starts again from 0, except that an install's
+    /// copy and extraction share one pass of the bar, weighted by size.
+    fn progress(&self, progress: u32, status: &str);
+    /// Shows that a step with no measurable progress is under way, such as 
+    /// looking for running processes or removing the old version, so the 
+    /// window doesn't look hung. The next `progress` call ends it.
+    fn busy(&self, _status: &str) {}
+    /// Asks a yes/no question. Reporters that cannot ask answer no.
+    fn confirm(&self, title: &str, prompt: &str) -> bool;
+    /// Reports the bytes written so far of the zip entry being extracted, 
+    /// as each chunk is written. `progress` already covers the archive as a
+    /// whole, so by default this does nothing.
+    fn entry_progress(&self, _entry_name: &str, _bytes_done: u64, 
+            _bytes_total: u64) {}
+}
+
+/// Writes rows to stdout, or stderr for ERROR and WARN. Used by --silent.
+/// With LogFormat::Json it stays quiet, as the JSON lines are the output.
+pub struct ConsoleReporter;
+
+impl ProgressReporter for ConsoleReporter {
+    fn message(&self, message_type: &str, time_str: &str, message: &str) {
+        if *LOG_FORMAT.lock().unwrap() == LogFormat::Json {
+            return;
+        }
+        if message_type == "ERROR" || message_type == "WARN" {
+            eprintln!("{} {:<5} {}", time_str, message_type, message);
+        } else {
+            println!("{} {:<5} {}", time_str, message_type, message);
+        }
+    }
+
+    fn progress(&self, _progress: u32, _status: &str) {}
+
+    fn confirm(&self, _title: &str, _prompt: &str) -> bool {
+        false
+    }
+}
@@ -0,0 +1,80 @@
+// 7-Zip archives, for the legacy bundles that were never repackaged as
+// zips. Only built with the sevenz feature; zips never come through here.
+
+use crate::zip_utils;
+use filetime::FileTime;
+use sevenz_rust::{Password, SevenZArchiveEntry, SevenZReader};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The names of the archive's entries, directories included.
+pub fn entry_names(archive_path: &Path) -> io::Result<Vec<String>> {
+    let reader = open(archive_path)?;
+    Ok(reader.archive().files.iter()
+        .map(|entry| entry.name().to_string())
+        .collect())
+}
+
+/// Extracts every entry below `extract_to_dir` and returns the entry count.
+/// `on_entry` is called with the entry's index, the count and its name
+/// before each one is written; returning false stops the extraction with an
+/// Interrupted error.
+pub fn extract_all(archive_path: &Path, extract_to_dir: &Path,
+        mut on_entry: impl FnMut(usize, usize, &str) -> bool)
+        -> io::Result<usize> {
+    let mut reader = open(archive_path)?;
+    let total = reader.archive().files.len();
+    let mut index = 0;
+    let mut result = Ok(());
+    reader.for_each_entries(|entry, data| {
+        if !on_entry(index, total, entry.name()) {
+            result = Err(io::Error::new(io::ErrorKind::Interrupted,
+                    "Extraction cancelled"));
+            return Ok(false);
+        }
+        index += 1;
+        if let Err(e) = extract_entry(entry, data, extract_to_dir) {
+            result = Err(io::Error::new(e.kind(),
+                    format!("{}: {}", entry.name(), e)));
+            return Ok(false);
+        }
+        Ok(true)
+    }).map_err(archive_error)?;
+    result.map(|()| total)
+}
+
+fn open(archive_path: &Path) -> io::Result<SevenZReader<File>> {
+    SevenZReader::open(archive_path, Password::empty()).map_err(archive_error)
+}
+
+/// Writes one entry, with the same path checks as a zip entry. The data's
+/// CRC is checked by the reader as it is read.
+fn extract_entry(entry: &SevenZArchiveEntry, data: &mut dyn Read,
+        extract_to_dir: &Path) -> io::Result<()> {
+    let path = zip_utils::join_entry_path(extract_to_dir, entry.name())?;
+    if entry.is_directory() {
+        fs::create_dir_all(&path)?;
+        zip_utils::check_within(extract_to_dir, &path, entry.name())?;
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+        zip_utils::check_within(extract_to_dir, parent, entry.name())?;
+    }
+    let mut output = File::create(&path)?;
+    io::copy(data, &mut output)?;
+    drop(output);
+    // As with zips, a file whose time can't be set is still usable.
+    if entry.has_last_modified_date {
+        let modified: SystemTime = entry.last_modified_date().into();
+        let time = FileTime::from_system_time(modified);
+        let _ = filetime::set_file_times(&path, time, time);
+    }
+    Ok(())
+}
+
+fn archive_error(e: sevenz_rust::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("7-Zip error: {}", e))
+}
@@ -0,0 +1,48 @@
+// Helpers shared by the unit tests.
+
+use crate::reporter::ProgressReporter;
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+
+/// An empty folder of its own for one test, under the system temp folder.
+/// The test deletes it when done; a failed test leaves it to look at.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("AppInstaller-test-{}-{}", 
+            name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Drops messages and progress and answers no to every question.
+pub struct NullReporter;
+
+impl ProgressReporter for NullReporter {
+    fn message(&self, _message_type: &str, _time_str: &str, _message: &str) {}
+
+    fn progress(&self, _progress: u32, _status: &str) {}
+
+    fn confirm(&self, _title: &str, _prompt: &str) -> bool {
+        false
+    }
+}
+
+/// Keeps every progress percentage reported, in order; otherwise like 
+/// NullReporter.
+#[derive(Default)]
+pub struct ProgressRecorder {
+    pub progress: RefCell<Vec<u32>>,
+}
+
+impl ProgressReporter for ProgressRecorder {
+    fn message(&self, _message_type: &str, _time_str: &str, _message: &str) {}
+
+    fn progress(&self, progress: u32, _status: &str) {
+        self.progress.borrow_mut().push(progress);
+    }
+
+    fn confirm(&self, _title: &str, _prompt: &str) -> bool {
+        false
+    }
+}
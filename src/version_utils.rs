@@ -0,0 +1,356 @@
+// Reads strings from an executable's version resource (the details shown in
+// Explorer's file properties) and parses versions out of zip file names.
+
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use winapi::shared::minwindef::{LPCVOID, LPVOID, UINT};
+use winapi::um::winver::{GetFileVersionInfoSizeW, GetFileVersionInfoW, 
+        VerQueryValueW};
+
+// Used when the resource has no translation table: US English, Unicode.
+const DEFAULT_TRANSLATION: (u16, u16) = (0x0409, 0x04b0);
+
+const VS_FFI_SIGNATURE: u32 = 0xFEEF04BD;
+
+// VS_FIXEDFILEINFO from verrsrc.h, which winapi does not expose.
+#[repr(C)]
+#[allow(non_snake_case, dead_code)]
+struct VS_FIXEDFILEINFO {
+    dwSignature: u32,
+    dwStrucVersion: u32,
+    dwFileVersionMS: u32,
+    dwFileVersionLS: u32,
+    dwProductVersionMS: u32,
+    dwProductVersionLS: u32,
+    dwFileFlagsMask: u32,
+    dwFileFlags: u32,
+    dwFileOS: u32,
+    dwFileType: u32,
+    dwFileSubtype: u32,
+    dwFileDateMS: u32,
+    dwFileDateLS: u32,
+}
+
+fn to_wide(s: &OsStr) -> Vec<u16> {
+    s.encode_wide().chain(Some(0)).collect()
+}
+
+fn read_version_info(exe: &Path) -> Option<Vec<u8>> {
+    let path = to_wide(exe.as_os_str());
+    let size = unsafe {
+        GetFileVersionInfoSizeW(path.as_ptr(), std::ptr::null_mut())
+    };
+    if size == 0 {
+        return None;
+    }
+    let mut data = vec![0u8; size as usize];
+    let result = unsafe {
+        GetFileVersionInfoW(path.as_ptr(), 0, size, 
+                data.as_mut_ptr() as LPVOID)
+    };
+    if result == 0 {
+        None
+    } else {
+        Some(data)
+    }
+}
+
+fn query_value(data: &[u8], sub_block: &str) -> Option<(LPVOID, UINT)> {
+    let sub_block = to_wide(OsStr::new(sub_block));
+    let mut buffer: LPVOID = std::ptr::null_mut();
+    let mut len: UINT = 0;
+    let result = unsafe {
+        VerQueryValueW(data.as_ptr() as LPCVOID, sub_block.as_ptr(), 
+                &mut buffer, &mut len)
+    };
+    if result == 0 || buffer.is_null() || len == 0 {
+        None
+    } else {
+        Some((buffer, len))
+    }
+}
+
+fn translations(data: &[u8]) -> Vec<(u16, u16)> {
+    let mut translations = Vec::new();
+    if let Some((buffer, len)) = query_value(data, r"\VarFileInfo\Translation") {
+        let words = unsafe {
+            std::slice::from_raw_parts(buffer as *const u16, len as usize / 2)
+        };
+        for pair in words.chunks_exact(2) {
+            translations.push((pair[0], pair[1]));
+        }
+    }
+    if !translations.contains(&DEFAULT_TRANSLATION) {
+        translations.push(DEFAULT_TRANSLATION);
+    }
+    translations
+}
+
+fn query_string(data: &[u8], name: &str) -> Option<String> {
+    for (language, code_page) in translations(data) {
+        let sub_block = format!(r"\StringFileInfo\{:04x}{:04x}\{}", 
+                language, code_page, name);
+        if let Some((buffer, len)) = query_value(data, &sub_block) {
+            // For strings the length is in characters, not bytes.
+            let chars = unsafe {
+                std::slice::from_raw_parts(buffer as *const u16, len as usize)
+            };
+            let value = String::from_utf16_lossy(chars);
+            let value = value.trim_end_matches('\0').trim();
+            if !value.is_empty() {
+                return Some(value.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Returns the ProductName from the exe's version resource, or its 
+/// FileDescription when no product name is set.
+pub fn product_name(exe: &Path) -> Option<String> {
+    let data = read_version_info(exe)?;
+    query_string(&data, "ProductName")
+        .or_else(|| query_string(&data, "FileDescription"))
+}
+
+/// The FileVersion from an exe's fixed version info, e.g. 1.4.0.12.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileVersion(pub [u16; 4]);
+
+impl fmt::Display for FileVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+pub fn file_version(exe: &Path) -> Option<FileVersion> {
+    let data = read_version_info(exe)?;
+    let (buffer, len) = query_value(&data, r"\")?;
+    if (len as usize) < std::mem::size_of::<VS_FIXEDFILEINFO>() {
+        return None;
+    }
+    let info = unsafe { &*(buffer as *const VS_FIXEDFILEINFO) };
+    if info.dwSignature != VS_FFI_SIGNATURE {
+        return None;
+    }
+    Some(FileVersion([
+        (info.dwFileVersionMS >> 16) as u16,
+        (info.dwFileVersionMS & 0xffff) as u16,
+        (info.dwFileVersionLS >> 16) as u16,
+        (info.dwFileVersionLS & 0xffff) as u16,
+    ]))
+}
+
+/// A semantic version parsed from a zip's file name. Build metadata is 
+/// dropped since it does not take part in precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Vec<String>,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| compare_pre_release(&self.pre, &other.pre))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+// A release sorts above any of its pre-releases. Otherwise identifiers are
+// compared in turn: numeric ones numerically and below alphanumeric ones, 
+// which compare as text. A shorter list that is a prefix sorts first.
+fn compare_pre_release(a: &[String], b: &[String]) -> Ordering {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        (false, false) => {}
+    }
+    for (x, y) in a.iter().zip(b.iter()) {
+        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Parses a bare version such as `1.2.10` or `2.0.0-beta.1`.
+pub fn parse_semver(text: &str) -> Option<Version> {
+    let text = text.split('+').next()?;
+    let (core, pre) = match text.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (text, None),
+    };
+
+    let mut numbers = core.split('.').map(|part| {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            None
+        } else {
+            part.parse::<u64>().ok()
+        }
+    });
+    let major = numbers.next()??;
+    let minor = numbers.next()??;
+    let patch = numbers.next()??;
+    if numbers.next().is_some() {
+        return None;
+    }
+
+    let pre = match pre {
+        Some(pre) => {
+            let identifiers: Vec<String> = pre.split('.')
+                .map(|s| s.to_string()).collect();
+            if identifiers.iter().any(|s| s.is_empty() || 
+                    !s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')) {
+                return None;
+            }
+            identifiers
+        }
+        None => Vec::new(),
+    };
+    Some(Version { major, minor, patch, pre })
+}
+
+/// Finds a semantic version in a file name without its extension, e.g. 
+/// `MyApp-1.2.10` or `MyApp_v2.0.0-beta.1`. The version must run to the end 
+/// of the name and start at a word boundary, optionally after a `v`.
+pub fn parse_version_from_filename(file_stem: &str) -> Option<Version> {
+    let bytes = file_stem.as_bytes();
+    for (i, b) in bytes.iter().enumerate() {
+        if !b.is_ascii_digit() {
+            continue;
+        }
+        let at_boundary = match i {
+            0 => true,
+            _ if !bytes[i - 1].is_ascii_alphanumeric() => true,
+            1 => matches!(bytes[0], b'v' | b'V'),
+            _ => matches!(bytes[i - 1], b'v' | b'V') && 
+                    !bytes[i - 2].is_ascii_alphanumeric(),
+        };
+        if at_boundary && let Some(version) = parse_semver(&file_stem[i..]) {
+            return Some(version);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_version_displays_and_orders_by_each_part() {
+        assert_eq!(FileVersion([1, 4, 0, 12]).to_string(), "1.4.0.12");
+        assert!(FileVersion([1, 4, 0, 12]) < FileVersion([1, 10, 0, 0]));
+        assert!(FileVersion([2, 0, 0, 0]) > FileVersion([1, 65535, 0, 0]));
+        assert!(FileVersion([1, 2, 3, 4]) < FileVersion([1, 2, 3, 5]));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn reads_the_version_resource_of_a_system_dll() {
+        let system_root = std::env::var_os("SystemRoot")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| r"C:\Windows".into());
+        let kernel32 = system_root.join(r"System32\kernel32.dll");
+        let version = file_version(&kernel32).unwrap();
+        assert!(version >= FileVersion([6, 0, 0, 0]), "{}", version);
+        assert!(product_name(&kernel32).is_some_and(|name| 
+                name.contains("Windows")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn file_without_a_version_resource_has_none() {
+        let dir = crate::test_utils::temp_dir("no-version");
+        let path = dir.join("Plain.exe");
+        std::fs::write(&path, b"MZ not really an exe").unwrap();
+        assert_eq!(file_version(&path), None);
+        assert_eq!(product_name(&path), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn version(major: u64, minor: u64, patch: u64, pre: &[&str]) -> Version {
+        Version { 
+            major, 
+            minor, 
+            patch, 
+            pre: pre.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_the_version_at_the_end_of_a_file_name() {
+        assert_eq!(parse_version_from_filename("MyApp-1.2.10"), 
+                Some(version(1, 2, 10, &[])));
+        assert_eq!(parse_version_from_filename("MyApp_v2.0.0-beta.1"), 
+                Some(version(2, 0, 0, &["beta", "1"])));
+        assert_eq!(parse_version_from_filename("1.0.0"), 
+                Some(version(1, 0, 0, &[])));
+    }
+
+    #[test]
+    fn drops_build_metadata() {
+        assert_eq!(parse_version_from_filename("MyApp-1.2.3+build.7"), 
+                Some(version(1, 2, 3, &[])));
+        assert_eq!(parse_semver("2.0.0-rc.1+20240101"), 
+                Some(version(2, 0, 0, &["rc", "1"])));
+    }
+
+    #[test]
+    fn rejects_names_without_a_full_version() {
+        assert_eq!(parse_version_from_filename("MyApp2.0"), None);
+        assert_eq!(parse_version_from_filename("MyApp-1.2"), None);
+        assert_eq!(parse_version_from_filename("MyApp-1.2.3-"), None);
+        assert_eq!(parse_version_from_filename("MyApp"), None);
+    }
+
+    #[test]
+    fn orders_numbers_numerically() {
+        assert!(version(1, 2, 10, &[]) > version(1, 2, 9, &[]));
+        assert!(version(1, 10, 0, &[]) > version(1, 9, 99, &[]));
+    }
+
+    #[test]
+    fn orders_pre_releases_as_semver_does() {
+        // The example from the Semantic Versioning spec, lowest first.
+        let versions: Vec<Version> = ["1.0.0-alpha", "1.0.0-alpha.1", 
+                "1.0.0-alpha.beta", "1.0.0-beta", "1.0.0-beta.2", 
+                "1.0.0-beta.11", "1.0.0-rc.1", "1.0.0"].iter()
+            .map(|text| parse_semver(text).unwrap())
+            .collect();
+        for pair in versions.windows(2) {
+            assert!(pair[0] < pair[1], "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(compare_pre_release(&[], &[]), Ordering::Equal);
+    }
+}
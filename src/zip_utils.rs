@@ -1,160 +1,1289 @@
 // It is a lot less code to use the Zip crate but it increases the executable
 // size significantly.
 
+use chrono::{Local, TimeZone};
+use filetime::FileTime;
 use flate2::read::DeflateDecoder;
+use flate2::{Crc, CrcWriter};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
 use std::fs::{self, OpenOptions};
-use std::io::{self, Write};
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
 
 pub struct ZipEntry {
     pub file_name: String,
-    pub compressed_size: u32,
+    pub flags: u16,
+    pub crc32: u32,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
     pub compression_method: u16,
-    pub local_header_offset: u32,
+    pub local_header_offset: u64,
+    /// From the entry's MS-DOS date and time, which are local time.
+    pub last_modified: Option<SystemTime>,
+    /// General purpose flag bit 0. Encrypted entries can't be extracted.
+    pub encrypted: bool,
+    /// The "version made by" field; its high byte is the host system, which
+    /// says how to read external_attributes.
+    pub version_made_by: u16,
+    /// Host-specific attributes. From a Unix host the high 16 bits are the
+    /// file's mode.
+    pub external_attributes: u32,
 }
 
-pub fn parse_central_directory(buffer: &[u8]) -> io::Result<Vec<ZipEntry>> {
-    let mut entries = Vec::new();
-    let mut i = 0;
-    const DEFLATE_SIGNATURE: &[u8] = b"\x50\x4b\x01\x02";
-
-    while i + 4 <= buffer.len() {
-        if &buffer[i..i + 4] == DEFLATE_SIGNATURE {
-            if i + 46 > buffer.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "Incomplete central directory header",
-                ));
+/// Why a zip could not be read, verified or extracted. Display gives a 
+/// message fit for the log; the variant says what kind of problem it was.
+#[derive(Debug)]
+pub enum ZipError {
+    /// Reading the archive or writing an extracted file failed.
+    Io(io::Error),
+    /// The archive ends inside the named structure.
+    Truncated(String),
+    /// No End of Central Directory record: not a zip, or cut short.
+    NotAZip,
+    /// A record doesn't start with its signature.
+    BadSignature { record: &'static str, offset: u64 },
+    /// Any other inconsistency in the archive's structure.
+    Invalid(String),
+    UnsupportedMethod(u16),
+    /// The named entry is encrypted.
+    Encrypted(String),
+    CrcMismatch { file_name: String, expected: u32, actual: u32 },
+    /// Only the low 32 bits of the size are compared; see check_crc.
+    SizeMismatch { file_name: String, expected: u64, actual: u32 },
+    /// The named entry, or its link target, lies outside the extraction
+    /// directory.
+    PathEscape(String),
+    /// A second file entry has this name, which would overwrite the first.
+    DuplicateEntry(String),
+}
+
+pub type ZipResult<T> = Result<T, ZipError>;
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZipError::Io(e) => write!(f, "{}", e),
+            ZipError::Truncated(what) => write!(f, "Incomplete {}", what),
+            ZipError::NotAZip => write!(f, 
+                    "End of central directory record not found; the file is \
+                    not a zip or is incomplete"),
+            ZipError::BadSignature { record, offset } => write!(f, 
+                    "Invalid {} signature at offset {}", record, offset),
+            ZipError::Invalid(message) => write!(f, "{}", message),
+            ZipError::UnsupportedMethod(method) => write!(f, 
+                    "Unsupported compression method: {}", method),
+            ZipError::Encrypted(file_name) => write!(f, 
+                    "{} is encrypted, which is not supported", file_name),
+            ZipError::CrcMismatch { file_name, expected, actual } => write!(f,
+                    "CRC-32 mismatch for {}: expected {:08x}, got {:08x}", 
+                    file_name, expected, actual),
+            ZipError::SizeMismatch { file_name, expected, actual } => write!(
+                    f, "Size mismatch for {}: expected {} bytes, got {}", 
+                    file_name, expected, actual),
+            ZipError::PathEscape(file_name) => write!(f, 
+                    "Entry {} would be extracted outside the target directory",
+                    file_name),
+            ZipError::DuplicateEntry(file_name) => write!(f, 
+                    "The zip has more than one entry named {}", file_name),
+        }
+    }
+}
+
+impl Error for ZipError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ZipError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ZipError {
+    fn from(e: io::Error) -> Self {
+        ZipError::Io(e)
+    }
+}
+
+/// For callers that deal in io::Result, such as the 7-Zip extraction that 
+/// shares join_entry_path and check_within.
+impl From<ZipError> for io::Error {
+    fn from(e: ZipError) -> Self {
+        let kind = match e {
+            ZipError::Io(inner) => return inner,
+            ZipError::Truncated(_) => io::ErrorKind::UnexpectedEof,
+            ZipError::UnsupportedMethod(_) | ZipError::Encrypted(_) => 
+                    io::ErrorKind::Unsupported,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// What an archive's first bytes say it is.
+pub enum ArchiveFormat {
+    Zip,
+    SevenZip,
+    /// Anything else, with a description when it is a format we recognise 
+    /// but can't install.
+    Other { name: Option<&'static str>, signature: Vec<u8> },
+}
+
+const SEVEN_ZIP_SIGNATURE: &[u8] = b"7z\xBC\xAF\x27\x1C";
+/// How many leading bytes detect_format reads, enough for any signature 
+/// below.
+const SIGNATURE_LEN: u64 = 6;
+const OTHER_SIGNATURES: &[(&[u8], &str)] = &[
+    (b"Rar!\x1A\x07", "a RAR archive"),
+    (b"\x1F\x8B", "a gzip file"),
+    (b"BZh", "a bzip2 file"),
+    (b"\xFD7zXZ\x00", "an xz file"),
+    (b"MSCF", "a cabinet file"),
+    (b"MZ", "an executable"),
+];
+
+/// Identifies the archive by its magic bytes, leaving the reader at the 
+/// start.
+pub fn detect_format<R: Read + Seek>(reader: &mut R) -> 
+        io::Result<ArchiveFormat> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut signature = Vec::new();
+    reader.by_ref().take(SIGNATURE_LEN).read_to_end(&mut signature)?;
+    reader.seek(SeekFrom::Start(0))?;
+    // Local file header, empty archive and spanned archive markers.
+    if signature.starts_with(b"PK") {
+        return Ok(ArchiveFormat::Zip);
+    }
+    if signature.starts_with(SEVEN_ZIP_SIGNATURE) {
+        return Ok(ArchiveFormat::SevenZip);
+    }
+    // A self-extracting exe is a stub with the zip appended.
+    if signature.starts_with(b"MZ") {
+        let appended_zip = find_central_directory(reader).is_ok();
+        reader.seek(SeekFrom::Start(0))?;
+        if appended_zip {
+            return Ok(ArchiveFormat::Zip);
+        }
+    }
+    let name = if signature.is_empty() {
+        Some("an empty file")
+    } else {
+        OTHER_SIGNATURES.iter()
+            .find(|(magic, _)| signature.starts_with(magic))
+            .map(|(_, name)| *name)
+    };
+    Ok(ArchiveFormat::Other { name, signature })
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveFormat::Zip => write!(f, "a zip file"),
+            ArchiveFormat::SevenZip => write!(f, "a 7-Zip archive"),
+            ArchiveFormat::Other { name, signature } => {
+                let hex: Vec<String> = signature.iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect();
+                write!(f, "{} (signature {})", 
+                        name.unwrap_or("an unknown format"), hex.join(" "))
             }
+        }
+    }
+}
+
+/// General purpose flag bit 11: the file name is UTF-8 rather than CP-437.
+const FLAG_UTF8: u16 = 0x0800;
+
+/// General purpose flag bit 0: the entry's data is encrypted.
+const FLAG_ENCRYPTED: u16 = 0x0001;
+
+/// General purpose flag bit 3: the CRC-32 and sizes follow the data in a 
+/// data descriptor and are zero in the local header.
+const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
+
+/// "Version made by" host whose external attributes hold a Unix mode.
+const HOST_UNIX: u16 = 3;
+const UNIX_FILE_TYPE_MASK: u32 = 0o170000;
+const UNIX_SYMLINK: u32 = 0o120000;
+
+impl ZipEntry {
+    pub fn has_data_descriptor(&self) -> bool {
+        self.flags & FLAG_DATA_DESCRIPTOR != 0
+    }
+
+    /// A symlink zipped on Unix. Its data is the link's target path.
+    pub fn is_symlink(&self) -> bool {
+        self.version_made_by >> 8 == HOST_UNIX && 
+            (self.external_attributes >> 16) & UNIX_FILE_TYPE_MASK == 
+                    UNIX_SYMLINK
+    }
+}
 
-            let compression_method = u16::from_le_bytes(buffer[i + 10..i + 12
-                    ].try_into().unwrap());
-            let compressed_size = u32::from_le_bytes(buffer[i + 20..i + 24
-                    ].try_into().unwrap());
-
-            let file_name_length =
-                u16::from_le_bytes(buffer[i + 28..i + 30].try_into().unwrap()) 
-                        as usize;
-            let extra_field_length =
-                u16::from_le_bytes(buffer[i + 30..i + 32].try_into().unwrap()) 
-                        as usize;
-            let file_comment_length =
-                u16::from_le_bytes(buffer[i + 32..i + 34].try_into().unwrap()) 
-                        as usize;
-            let local_header_offset =
-                u32::from_le_bytes(buffer[i + 42..i + 46].try_into().unwrap());
-
-            let header_size = 46;
-            let total_len = file_name_length + extra_field_length + 
-                    file_comment_length;
-            let start = i + header_size;
-            let end = start + total_len;
-
-            if end > buffer.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "Incomplete file name or extra fields",
-                ));
+/// Code page 437 characters 0x80 to 0xFF. The lower half is ASCII.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+    'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+    'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Decodes an entry name: strictly as UTF-8 when the UTF-8 flag is set, 
+/// otherwise as CP-437, the zip format's original encoding. Some Windows 
+/// tools separate folders with `\` rather than the `/` the format requires,
+/// so those become `/` too; join_entry_path still rejects `..` and roots.
+fn decode_file_name(name: &[u8], flags: u16) -> ZipResult<String> {
+    let name = if flags & FLAG_UTF8 != 0 {
+        String::from_utf8(name.to_vec()).map_err(|_| ZipError::Invalid(
+                format!("File name {:?} is not valid UTF-8", 
+                        String::from_utf8_lossy(name))))?
+    } else {
+        name.iter()
+            .map(|&b| if b < 0x80 { 
+                b as char 
+            } else { 
+                CP437_HIGH[b as usize - 0x80] 
+            })
+            .collect()
+    };
+    Ok(name.replace('\\', "/"))
+}
+
+/// Converts an MS-DOS date and time (2-second resolution, local time) to a
+/// SystemTime. Returns None for invalid or zero dates.
+fn dos_date_time(date: u16, time: u16) -> Option<SystemTime> {
+    let year = 1980 + (date >> 9) as i32;
+    let month = ((date >> 5) & 0x0F) as u32;
+    let day = (date & 0x1F) as u32;
+    let hour = (time >> 11) as u32;
+    let minute = ((time >> 5) & 0x3F) as u32;
+    let second = ((time & 0x1F) * 2) as u32;
+    Local.with_ymd_and_hms(year, month, day, hour, minute, second)
+        .earliest()
+        .map(SystemTime::from)
+}
+
+const ZIP64_SENTINEL: u32 = 0xFFFFFFFF;
+const ZIP64_COUNT_SENTINEL: u16 = 0xFFFF;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+const EOCD_SIGNATURE: &[u8] = b"\x50\x4b\x05\x06";
+const EOCD_SIZE: u64 = 22;
+const ZIP64_EOCD_LOCATOR_SIGNATURE: &[u8] = b"\x50\x4b\x06\x07";
+const ZIP64_EOCD_LOCATOR_SIZE: u64 = 20;
+const ZIP64_EOCD_SIGNATURE: &[u8] = b"\x50\x4b\x06\x06";
+/// The Zip64 End of Central Directory record without extensible data.
+const ZIP64_EOCD_SIZE: u64 = 56;
+const MAX_COMMENT_LENGTH: u64 = 0xFFFF;
+
+/// Replaces 32-bit central directory values that hold the 0xFFFFFFFF 
+/// sentinel with the 64-bit values from the Zip64 extended information extra
+/// field. The field only stores the values that overflowed, in this order.
+fn apply_zip64_extra(extra: &[u8], uncompressed_size: &mut u64, 
+        compressed_size: &mut u64, local_header_offset: &mut u64) 
+        -> ZipResult<()> {
+    let mut i = 0;
+    while extra.len() - i >= 4 {
+        let header_id = u16::from_le_bytes(extra[i..i + 2].try_into().unwrap());
+        let data_size = u16::from_le_bytes(extra[i + 2..i + 4].try_into(
+                ).unwrap()) as usize;
+        let data_start = i + 4;
+        let data_end = match data_start.checked_add(data_size) {
+            Some(data_end) if data_end <= extra.len() => data_end,
+            _ => break,
+        };
+
+        if header_id == ZIP64_EXTRA_FIELD_ID {
+            let mut data = &extra[data_start..data_end];
+            for value in [uncompressed_size, compressed_size, 
+                    local_header_offset] {
+                if *value != ZIP64_SENTINEL as u64 {
+                    continue;
+                }
+                if data.len() < 8 {
+                    return Err(ZipError::Truncated(
+                            "Zip64 extra field".to_string()));
+                }
+                *value = u64::from_le_bytes(data[..8].try_into().unwrap());
+                data = &data[8..];
             }
+            return Ok(());
+        }
+        i = data_end;
+    }
+    Ok(())
+}
 
-            let file_name =
-                String::from_utf8_lossy(&buffer[start..start + 
-                        file_name_length]).to_string();
+/// Reads the entries of a zip without loading the whole file: the End of 
+/// Central Directory record is found in the file's tail and only the central
+/// directory it points at is read into memory. Two file entries with the 
+/// same name fail with DuplicateEntry, since the second would silently 
+/// replace the first.
+pub fn read_central_directory<R: Read + Seek>(reader: &mut R) 
+        -> ZipResult<Vec<ZipEntry>> {
+    let (entries, duplicates) = read_central_directory_keep_first(reader)?;
+    match duplicates.into_iter().next() {
+        Some(file_name) => Err(ZipError::DuplicateEntry(file_name)),
+        None => Ok(entries),
+    }
+}
+
+/// read_central_directory, but of entries sharing a name only the first is
+/// kept; the names of those dropped are returned with the entries.
+pub fn read_central_directory_keep_first<R: Read + Seek>(reader: &mut R) 
+        -> ZipResult<(Vec<ZipEntry>, Vec<String>)> {
+    let (cd_offset, cd_size, entry_count, base) = 
+            find_central_directory(reader)?;
+    // The sizes come from the file: check them before reading.
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    if cd_offset.checked_add(cd_size).is_none_or(|end| end > file_len) {
+        return Err(ZipError::Truncated("central directory".to_string()));
+    }
+    let mut buffer = Vec::new();
+    reader.seek(SeekFrom::Start(cd_offset))?;
+    reader.take(cd_size).read_to_end(&mut buffer)?;
+    if (buffer.len() as u64) < cd_size {
+        return Err(ZipError::Truncated("central directory".to_string()));
+    }
+    let (mut entries, duplicates) = parse_central_directory(&buffer, 
+            entry_count)?;
+    for entry in &mut entries {
+        entry.local_header_offset = entry.local_header_offset
+            .checked_add(base)
+            .ok_or_else(|| ZipError::Invalid(format!(
+                    "Local header offset out of range for {}", 
+                    entry.file_name)))?;
+    }
+    Ok((entries, duplicates))
+}
+
+/// Returns the offset and size of the central directory and its number of 
+/// entries, taken from the End of Central Directory record or, when that 
+/// holds the 0xFFFF(FFFF) sentinel, from the Zip64 End of Central Directory 
+/// record, followed by the archive's base offset. 
+///
+/// The base is nonzero for a zip appended to something else, such as a 
+/// self-extracting exe's stub: the offsets the records hold count from the
+/// start of the zip, not of the file. The central directory ends where the 
+/// record after it starts, so the difference between where it really is 
+/// and where the record says it is gives the base. The returned offset 
+/// already includes it; entries' local header offsets need it added.
+fn find_central_directory<R: Read + Seek>(reader: &mut R) 
+        -> ZipResult<(u64, u64, u64, u64)> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let tail_len = file_len.min(EOCD_SIZE + MAX_COMMENT_LENGTH);
+    let tail_start = file_len - tail_len;
+    let mut tail = Vec::new();
+    reader.seek(SeekFrom::Start(tail_start))?;
+    reader.take(tail_len).read_to_end(&mut tail)?;
+
+    // The comment may itself contain the signature, so a match only counts 
+    // when its comment length runs exactly to the end of the file.
+    let eocd_pos = (0..tail.len().saturating_sub(EOCD_SIZE as usize - 1))
+        .rev()
+        .find(|&i| &tail[i..i + 4] == EOCD_SIGNATURE && 
+                i + EOCD_SIZE as usize + u16::from_le_bytes(
+                tail[i + 20..i + 22].try_into().unwrap()) as usize == 
+                tail.len())
+        .ok_or(ZipError::NotAZip)?;
+    let eocd = &tail[eocd_pos..];
+    let entry_count = u16::from_le_bytes(eocd[10..12].try_into().unwrap());
+    let cd_size = u32::from_le_bytes(eocd[12..16].try_into().unwrap());
+    let cd_offset = u32::from_le_bytes(eocd[16..20].try_into().unwrap());
+    let eocd_offset = tail_start + eocd_pos as u64;
+    if entry_count != ZIP64_COUNT_SENTINEL && cd_size != ZIP64_SENTINEL && 
+            cd_offset != ZIP64_SENTINEL {
+        let base = archive_base(eocd_offset, cd_offset as u64, cd_size as u64);
+        // Both are at most the EOCD's offset, so this can't overflow.
+        return Ok((cd_offset as u64 + base, cd_size as u64, 
+                entry_count as u64, base));
+    }
+
+    let locator_error = ZipError::BadSignature { 
+        record: "Zip64 end of central directory locator", 
+        offset: eocd_offset.saturating_sub(ZIP64_EOCD_LOCATOR_SIZE),
+    };
+    if eocd_offset < ZIP64_EOCD_LOCATOR_SIZE {
+        return Err(locator_error);
+    }
+    let mut locator = [0u8; ZIP64_EOCD_LOCATOR_SIZE as usize];
+    reader.seek(SeekFrom::Start(eocd_offset - ZIP64_EOCD_LOCATOR_SIZE))?;
+    reader.read_exact(&mut locator)?;
+    if &locator[0..4] != ZIP64_EOCD_LOCATOR_SIGNATURE {
+        return Err(locator_error);
+    }
 
-            entries.push(ZipEntry {
-                file_name,
-                compressed_size,
-                compression_method,
-                local_header_offset,
+    // The locator's offset is relative to the zip too. Without extensible 
+    // data the Zip64 record sits right before the locator, so a record 
+    // found there instead of at the stated offset gives the base.
+    let stated_offset = u64::from_le_bytes(locator[8..16].try_into(
+            ).unwrap());
+    let adjacent_offset = (eocd_offset - ZIP64_EOCD_LOCATOR_SIZE)
+        .checked_sub(ZIP64_EOCD_SIZE)
+        .filter(|&offset| offset > stated_offset);
+    let mut zip64_eocd = [0u8; ZIP64_EOCD_SIZE as usize];
+    let zip64_eocd_offset = match Some(stated_offset).into_iter()
+            .chain(adjacent_offset)
+            .find(|&offset| read_zip64_eocd(reader, offset, &mut zip64_eocd)) {
+        Some(offset) => offset,
+        None => return Err(ZipError::BadSignature { 
+            record: "Zip64 end of central directory", 
+            offset: stated_offset,
+        }),
+    };
+    let entry_count = u64::from_le_bytes(zip64_eocd[32..40].try_into(
+            ).unwrap());
+    let cd_size = u64::from_le_bytes(zip64_eocd[40..48].try_into().unwrap());
+    let cd_offset = u64::from_le_bytes(zip64_eocd[48..56].try_into(
+            ).unwrap());
+    let base = archive_base(zip64_eocd_offset, cd_offset, cd_size);
+    let cd_offset = cd_offset.checked_add(base).ok_or_else(|| 
+            ZipError::Invalid("Central directory offset out of range"
+                .to_string()))?;
+    Ok((cd_offset, cd_size, entry_count, base))
+}
+
+/// Reads a Zip64 End of Central Directory record at `offset` into `record`,
+/// returning whether one was there.
+fn read_zip64_eocd<R: Read + Seek>(reader: &mut R, offset: u64, 
+        record: &mut [u8; ZIP64_EOCD_SIZE as usize]) -> bool {
+    reader.seek(SeekFrom::Start(offset)).is_ok() && 
+            reader.read_exact(record).is_ok() && 
+            &record[0..4] == ZIP64_EOCD_SIGNATURE
+}
+
+/// How far into the file the zip starts, given where the record that 
+/// follows the central directory really is and the central directory's 
+/// stated offset and size. Zero for an ordinary zip, and for a damaged one 
+/// whose directory would have to start before its stated offset, which is
+/// left for the later checks to report.
+fn archive_base(cd_end: u64, cd_offset: u64, cd_size: u64) -> u64 {
+    cd_end.saturating_sub(cd_size).saturating_sub(cd_offset)
+}
+
+/// Where a field of `len` bytes at `start` ends, failing with Truncated 
+/// when that is past the end of `buffer`. The lengths come from the file, 
+/// so the sum is checked rather than trusted not to overflow.
+fn field_end(buffer: &[u8], start: usize, len: usize, field: &str) 
+        -> ZipResult<usize> {
+    match start.checked_add(len) {
+        Some(end) if end <= buffer.len() => Ok(end),
+        _ => Err(ZipError::Truncated(field.to_string())),
+    }
+}
+
+/// Reads `entry_count` file headers back to back from the start of the 
+/// central directory. Each header's lengths say where the next one starts, 
+/// so nothing is found by searching for signatures. A file entry whose name
+/// matches an earlier one, ignoring case as Windows does, is left out and 
+/// its name returned in the second list. Repeated folder entries are 
+/// harmless and kept.
+fn parse_central_directory(buffer: &[u8], entry_count: u64) 
+        -> ZipResult<(Vec<ZipEntry>, Vec<String>)> {
+    // The count comes from the file; don't trust it for the allocation.
+    let mut entries = Vec::with_capacity(entry_count.min(
+            buffer.len() as u64 / 46) as usize);
+    let mut names = HashSet::new();
+    let mut duplicates = Vec::new();
+    let mut i = 0;
+    const CENTRAL_HEADER_SIGNATURE: &[u8] = b"\x50\x4b\x01\x02";
+
+    for _ in 0..entry_count {
+        field_end(buffer, i, 46, "central directory header")?;
+        if &buffer[i..i + 4] != CENTRAL_HEADER_SIGNATURE {
+            // Relative to the central directory's start.
+            return Err(ZipError::BadSignature { 
+                record: "central directory header", 
+                offset: i as u64,
             });
+        }
 
-            i = end;
-        } else {
-            i += 1;
+        let version_made_by = u16::from_le_bytes(buffer[i + 4..i + 6
+                ].try_into().unwrap());
+        let flags = u16::from_le_bytes(buffer[i + 8..i + 10
+                ].try_into().unwrap());
+        let compression_method = u16::from_le_bytes(buffer[i + 10..i + 12
+                ].try_into().unwrap());
+        let dos_time = u16::from_le_bytes(buffer[i + 12..i + 14
+                ].try_into().unwrap());
+        let dos_date = u16::from_le_bytes(buffer[i + 14..i + 16
+                ].try_into().unwrap());
+        let crc32 = u32::from_le_bytes(buffer[i + 16..i + 20
+                ].try_into().unwrap());
+        let mut compressed_size = u32::from_le_bytes(buffer[i + 20..i + 24
+                ].try_into().unwrap()) as u64;
+        let mut uncompressed_size = u32::from_le_bytes(buffer[i + 24..i + 28
+                ].try_into().unwrap()) as u64;
+
+        let file_name_length =
+            u16::from_le_bytes(buffer[i + 28..i + 30].try_into().unwrap()) 
+                    as usize;
+        let extra_field_length =
+            u16::from_le_bytes(buffer[i + 30..i + 32].try_into().unwrap()) 
+                    as usize;
+        let file_comment_length =
+            u16::from_le_bytes(buffer[i + 32..i + 34].try_into().unwrap()) 
+                    as usize;
+        let external_attributes = u32::from_le_bytes(buffer[i + 38..i + 42
+                ].try_into().unwrap());
+        let mut local_header_offset =
+            u32::from_le_bytes(buffer[i + 42..i + 46].try_into().unwrap()) 
+                    as u64;
+
+        let start = i + 46;
+        let extra_start = field_end(buffer, start, file_name_length, 
+                "file name")?;
+        let comment_start = field_end(buffer, extra_start, 
+                extra_field_length, "extra fields")?;
+        let end = field_end(buffer, comment_start, file_comment_length, 
+                "file comment")?;
+
+        let file_name = decode_file_name(&buffer[start..extra_start], flags)?;
+
+        apply_zip64_extra(
+            &buffer[extra_start..comment_start],
+            &mut uncompressed_size,
+            &mut compressed_size,
+            &mut local_header_offset,
+        )?;
+        i = end;
+
+        if !file_name.ends_with('/') && 
+                !names.insert(file_name.to_lowercase()) {
+            duplicates.push(file_name);
+            continue;
         }
+        entries.push(ZipEntry {
+            file_name,
+            flags,
+            crc32,
+            compressed_size,
+            uncompressed_size,
+            compression_method,
+            local_header_offset,
+            last_modified: dos_date_time(dos_date, dos_time),
+            encrypted: flags & FLAG_ENCRYPTED != 0,
+            version_made_by,
+            external_attributes,
+        });
     }
 
-    Ok(entries)
+    Ok((entries, duplicates))
 }
 
-pub fn extract_file(entry: &ZipEntry, buffer: &[u8], extract_to_dir: &Path) -> 
-        io::Result<()> {
-    let offset = entry.local_header_offset as usize;
-
-    if offset + 30 > buffer.len() {
-        return Err(io::Error::new(
-            io::ErrorKind::UnexpectedEof,
-            "Incomplete local header",
-        ));
+/// Positions the reader at the entry's compressed data and returns a reader
+/// limited to it. Only the name and extra field lengths are taken from the 
+/// local header; the CRC-32 and sizes always come from the central 
+/// directory, since entries written with a data descriptor (see 
+/// has_data_descriptor) leave them zero there.
+fn entry_data<'a, R: Read + Seek>(entry: &ZipEntry, reader: &'a mut R) 
+        -> ZipResult<io::Take<&'a mut R>> {
+    let offset = entry.local_header_offset;
+    let mut header = [0u8; 30];
+    reader.seek(SeekFrom::Start(offset))?;
+    if let Err(e) = reader.read_exact(&mut header) {
+        return Err(match e.kind() {
+            io::ErrorKind::UnexpectedEof => ZipError::Truncated(
+                    format!("local header of {}", entry.file_name)),
+            _ => e.into(),
+        });
     }
 
-    if &buffer[offset..offset + 4] != b"\x50\x4b\x03\x04" {
-        eprintln!(
-            "Invalid local header signature at offset {}: {:?}",
+    if &header[0..4] != b"\x50\x4b\x03\x04" {
+        return Err(ZipError::BadSignature { 
+            record: "local file header", 
             offset,
-            &buffer[offset..offset + 4]
-        );
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Invalid local file header signature",
-        ));
+        });
+    }
+
+    // Without a data descriptor the local header repeats the compressed 
+    // size, so a mismatch means the offset or the archive is wrong.
+    let local_compressed_size = u32::from_le_bytes(header[18..22].try_into(
+            ).unwrap());
+    if !entry.has_data_descriptor() && 
+            local_compressed_size != ZIP64_SENTINEL && 
+            local_compressed_size as u64 != entry.compressed_size {
+        return Err(ZipError::Invalid(
+                format!("Local header size mismatch for {}", entry.file_name)));
     }
 
     let file_name_length =
-        u16::from_le_bytes(buffer[offset + 26..offset + 28].try_into().unwrap(
-                )) as usize;
+        u16::from_le_bytes(header[26..28].try_into().unwrap()) as usize;
     let extra_field_length =
-        u16::from_le_bytes(buffer[offset + 28..offset + 30].try_into().unwrap(
-                )) as usize;
-
-    let data_start = offset + 30 + file_name_length + extra_field_length;
-    let data_end = data_start + entry.compressed_size as usize;
+        u16::from_le_bytes(header[28..30].try_into().unwrap()) as i64;
+    // The data must end within u64 for the reads below to make sense.
+    let data_end = offset.checked_add(30 + file_name_length as u64 + 
+            extra_field_length as u64)
+        .and_then(|data_start| data_start.checked_add(entry.compressed_size));
+    if data_end.is_none() {
+        return Err(ZipError::Invalid(
+                format!("Compressed size out of range for {}", 
+                        entry.file_name)));
+    }
 
-    if data_end > buffer.len() {
-        return Err(io::Error::new(
-            io::ErrorKind::UnexpectedEof,
-            "File data exceeds buffer",
-        ));
+    // The local header repeats the name; a different one means the offset 
+    // points at another entry's data.
+    let mut file_name = vec![0u8; file_name_length];
+    reader.read_exact(&mut file_name)?;
+    let local_flags = u16::from_le_bytes(header[6..8].try_into().unwrap());
+    let file_name = decode_file_name(&file_name, local_flags)?;
+    if file_name != entry.file_name {
+        return Err(ZipError::Invalid(
+                format!("Local header name {:?} does not match {}", 
+                        file_name, entry.file_name)));
     }
 
-    let file_data = &buffer[data_start..data_end];
-    let path = extract_to_dir.join(&entry.file_name);
+    reader.seek(SeekFrom::Current(extra_field_length))?;
+    Ok(reader.take(entry.compressed_size))
+}
+
+/// Extracts one entry below `extract_to_dir` and returns the path written.
+pub fn extract_file<R: Read + Seek>(entry: &ZipEntry, reader: &mut R, 
+        extract_to_dir: &Path) -> ZipResult<PathBuf> {
+    extract_file_with_progress(entry, reader, extract_to_dir, |_| {})
+}
+
+/// extract_file, calling `on_progress` with the number of bytes written so
+/// far (out of `entry.uncompressed_size`) as each chunk of the entry is 
+/// written.
+pub fn extract_file_with_progress<R: Read + Seek>(entry: &ZipEntry, 
+        reader: &mut R, extract_to_dir: &Path, on_progress: impl FnMut(u64)) 
+        -> ZipResult<PathBuf> {
+    let path = join_entry_path(extract_to_dir, &entry.file_name)?;
 
     // Handle directories
     if entry.file_name.ends_with('/') {
         fs::create_dir_all(&path)?;
-        return Ok(());
+        check_within(extract_to_dir, &path, &entry.file_name)?;
+        return Ok(path);
     }
 
+    check_not_encrypted(entry)?;
+    check_method(entry)?;
+    let file_data = entry_data(entry, reader)?;
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
+        check_within(extract_to_dir, parent, &entry.file_name)?;
     }
 
-    let mut output = OpenOptions::new()
+    let output = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
         .open(&path)?;
 
+    let mut output = CrcWriter::new(ProgressWriter { 
+        inner: output, 
+        written: 0, 
+        on_progress,
+    });
+    decompress_to(entry, file_data, &mut output)?;
+    check_crc(entry, output.crc())?;
+    Ok(path)
+}
+
+/// Passes writes through, telling `on_progress` the running total.
+struct ProgressWriter<W, F> {
+    inner: W,
+    written: u64,
+    on_progress: F,
+}
+
+impl<W: Write, F: FnMut(u64)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.written += count as u64;
+        (self.on_progress)(self.written);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// How extract_symlink recreated a link.
+#[derive(Debug, PartialEq)]
+pub enum LinkKind {
+    Symlink,
+    /// A directory link made without the symlink privilege.
+    Junction,
+    /// A copy of the target file, since this account may not create 
+    /// symlinks.
+    Copy,
+}
+
+/// Windows' error when creating a symlink needs admin rights or Developer 
+/// Mode.
+const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+/// Recreates a symlink entry below `extract_to_dir`. Call it once the other
+/// entries are extracted, so that the target exists and it is known whether
+/// it is a directory. A target outside `extract_to_dir` is refused. Without
+/// the symlink privilege a directory gets a junction and a file a copy of 
+/// its target.
+pub fn extract_symlink<R: Read + Seek>(entry: &ZipEntry, reader: &mut R, 
+        extract_to_dir: &Path) -> ZipResult<LinkKind> {
+    check_not_encrypted(entry)?;
+    let mut target = Vec::new();
+    let mut output = CrcWriter::new(&mut target);
+    decompress_to(entry, entry_data(entry, reader)?, &mut output)?;
+    check_crc(entry, output.crc())?;
+    let target = String::from_utf8(target).map_err(|_| ZipError::Invalid(
+            format!("Link target of {} is not UTF-8", entry.file_name)))?;
+
+    let link_name = entry.file_name.trim_end_matches('/');
+    let link_path = join_entry_path(extract_to_dir, link_name)?;
+    let link_dir = match link_name.rsplit_once('/') {
+        Some((dir, _)) => dir,
+        None => "",
+    };
+    let target_path = join_entry_path(extract_to_dir, 
+            &resolve_link_target(link_dir, &target, &entry.file_name)?)?;
+    if let Some(parent) = link_path.parent() {
+        fs::create_dir_all(parent)?;
+        check_within(extract_to_dir, parent, &entry.file_name)?;
+    }
+
+    let relative_target = target.replace('/', "\\");
+    let is_dir = target_path.is_dir();
+    let linked = if is_dir {
+        std::os::windows::fs::symlink_dir(&relative_target, &link_path)
+    } else {
+        std::os::windows::fs::symlink_file(&relative_target, &link_path)
+    };
+    match linked {
+        Ok(()) => Ok(LinkKind::Symlink),
+        Err(e) if e.raw_os_error() != Some(ERROR_PRIVILEGE_NOT_HELD) => 
+                Err(e.into()),
+        Err(_) if is_dir => {
+            junction::create(&target_path, &link_path)?;
+            Ok(LinkKind::Junction)
+        }
+        Err(_) => {
+            fs::copy(&target_path, &link_path)?;
+            Ok(LinkKind::Copy)
+        }
+    }
+}
+
+/// Resolves a link's target, given with `/` separators and relative to the
+/// link's directory `link_dir`, to a path relative to the extraction 
+/// directory. Absolute targets and ones that climb out are refused.
+fn resolve_link_target(link_dir: &str, target: &str, file_name: &str) 
+        -> ZipResult<String> {
+    if target.starts_with('/') || target.starts_with('\\') || 
+            target.contains(':') {
+        return Err(ZipError::PathEscape(file_name.to_string()));
+    }
+    let mut parts: Vec<&str> = link_dir.split('/')
+        .filter(|part| !part.is_empty())
+        .collect();
+    for part in target.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(ZipError::PathEscape(file_name.to_string()));
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// Gives an extracted file the entry's modification time (and the same 
+/// access time). Directories are left alone, since extracting their 
+/// contents changes them anyway.
+pub fn set_modified_time(entry: &ZipEntry, path: &Path) -> io::Result<()> {
+    if entry.file_name.ends_with('/') {
+        return Ok(());
+    }
+    match entry.last_modified {
+        Some(modified) => {
+            let time = FileTime::from_system_time(modified);
+            filetime::set_file_times(path, time, time)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Paths at least this long get the `\\?\` prefix. CreateDirectory's limit
+/// is MAX_PATH (260) minus room for an 8.3 file name.
+const MAX_SHORT_PATH_LEN: usize = 248;
+
+/// Joins an entry name onto the extraction directory, rejecting absolute 
+/// paths, drive letters and `..` components. Paths too long for the plain 
+/// Win32 form are rebuilt on the canonicalized (`\\?\`) directory so deep 
+/// trees still extract.
+pub(crate) fn join_entry_path(extract_to_dir: &Path, file_name: &str) -> 
+        ZipResult<PathBuf> {
+    let relative = Path::new(file_name);
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            _ => return Err(ZipError::PathEscape(file_name.to_string())),
+        }
+    }
+    let path = extract_to_dir.join(relative);
+    if path.as_os_str().len() < MAX_SHORT_PATH_LEN {
+        return Ok(path);
+    }
+
+    // Extended-length paths are not normalized by Windows, so every 
+    // separator must be a backslash and `.` components must go.
+    fs::create_dir_all(extract_to_dir)?;
+    let mut long_path = fs::canonicalize(extract_to_dir)?;
+    for component in relative.components() {
+        if let Component::Normal(name) = component {
+            long_path.push(name);
+        }
+    }
+    Ok(long_path)
+}
+
+/// Checks that an existing path resolves inside the extraction directory. 
+/// Both sides are canonicalized, so a root reached through a junction or 
+/// symlink is compared by its real location.
+pub(crate) fn check_within(extract_to_dir: &Path, path: &Path, 
+        file_name: &str) -> ZipResult<()> {
+    let root = fs::canonicalize(extract_to_dir)?;
+    if fs::canonicalize(path)?.starts_with(&root) {
+        Ok(())
+    } else {
+        Err(ZipError::PathEscape(file_name.to_string()))
+    }
+}
+
+/// Decodes an entry and checks the result against the sizes and CRC-32 from 
+/// the central directory without writing anything to disk.
+pub fn verify_file<R: Read + Seek>(entry: &ZipEntry, reader: &mut R) 
+        -> ZipResult<()> {
+    if entry.file_name.ends_with('/') {
+        // Nothing to decode, but its local header is still checked.
+        return entry_data(entry, reader).map(|_| ());
+    }
+    check_not_encrypted(entry)?;
+    check_method(entry)?;
+    let file_data = entry_data(entry, reader)?;
+
+    let mut output = CrcWriter::new(io::sink());
+    decompress_to(entry, file_data, &mut output)?;
+
+    check_crc(entry, output.crc())
+}
+
+/// Fails with UnsupportedMethod before anything is read or created for an 
+/// entry that decompress_to couldn't handle.
+fn check_method(entry: &ZipEntry) -> ZipResult<()> {
+    let supported = match entry.compression_method {
+        0 | 8 => true,
+        12 => cfg!(feature = "bzip2"),
+        14 => cfg!(feature = "lzma"),
+        _ => false,
+    };
+    if !supported {
+        return Err(ZipError::UnsupportedMethod(entry.compression_method));
+    }
+    Ok(())
+}
+
+/// Encrypted data would decode to garbage, so it is refused up front.
+fn check_not_encrypted(entry: &ZipEntry) -> ZipResult<()> {
+    if entry.encrypted {
+        return Err(ZipError::Encrypted(entry.file_name.clone()));
+    }
+    Ok(())
+}
+
+fn check_crc(entry: &ZipEntry, crc: &Crc) -> ZipResult<()> {
+    // Crc counts bytes modulo 2^32, so compare the low 32 bits only.
+    if crc.amount() != entry.uncompressed_size as u32 {
+        return Err(ZipError::SizeMismatch {
+            file_name: entry.file_name.clone(),
+            expected: entry.uncompressed_size,
+            actual: crc.amount(),
+        });
+    }
+    if crc.sum() != entry.crc32 {
+        return Err(ZipError::CrcMismatch {
+            file_name: entry.file_name.clone(),
+            expected: entry.crc32,
+            actual: crc.sum(),
+        });
+    }
+    Ok(())
+}
+
+/// Zip stores LZMA data behind a 4-byte header (LZMA SDK version and the 
+/// size of the properties that follow) and leaves the uncompressed size out
+/// of the LZMA header, taking it from the central directory instead.
+#[cfg(feature = "lzma")]
+fn decompress_lzma<R: Read, W: Write>(entry: &ZipEntry, mut file_data: R, 
+        output: &mut W) -> ZipResult<()> {
+    let mut header = [0u8; 4];
+    if file_data.read_exact(&mut header).is_err() {
+        return Err(ZipError::Truncated(
+                format!("LZMA header for {}", entry.file_name)));
+    }
+    let options = lzma_rs::decompress::Options {
+        unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(
+                Some(entry.uncompressed_size)),
+        ..Default::default()
+    };
+    let mut input = io::BufReader::new(file_data);
+    lzma_rs::lzma_decompress_with_options(&mut input, output, &options)
+        .map_err(|e| ZipError::Invalid(
+                format!("LZMA error in {}: {}", entry.file_name, e)))
+}
+
+fn decompress_to<R: Read, W: Write>(entry: &ZipEntry, mut file_data: R, 
+        output: &mut W) -> ZipResult<()> {
     match entry.compression_method {
         0 => {
             // Stored (no compression)
-            output.write_all(file_data)?;
+            io::copy(&mut file_data, output)?;
         }
         8 => {
             // Deflate compression
             let mut decoder = DeflateDecoder::new(file_data);
-            io::copy(&mut decoder, &mut output)?;
+            io::copy(&mut decoder, output)?;
+        }
+        #[cfg(feature = "bzip2")]
+        12 => {
+            // BZIP2 compression
+            let mut decoder = bzip2::read::BzDecoder::new(file_data);
+            io::copy(&mut decoder, output)?;
+        }
+        #[cfg(feature = "lzma")]
+        14 => {
+            // LZMA compression
+            decompress_lzma(entry, file_data, output)?;
         }
         _ => {
-            return Err(io::Error::new(
-                io::ErrorKind::Unsupported,
-                format!(
-                    "Unsupported compression method: {}",
-                    entry.compression_method
-                ),
-            ));
+            return Err(ZipError::UnsupportedMethod(entry.compression_method));
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+    use std::io::Cursor;
+
+    /// An entry for test_zip.
+    struct TestEntry<'a> {
+        name: &'a [u8],
+        data: &'a [u8],
+        flags: u16,
+        /// 0 (stored) or 8 (deflated).
+        method: u16,
+        /// A Unix mode for the external attributes; zero for a DOS entry.
+        unix_mode: u32,
+    }
+
+    fn entry<'a>(name: &'a str, data: &'a [u8]) -> TestEntry<'a> {
+        TestEntry { 
+            name: name.as_bytes(), 
+            data, 
+            flags: 0, 
+            method: 0, 
+            unix_mode: 0,
+        }
+    }
+
+    /// A zip of `entries` with correct CRC-32s and sizes. An entry with the
+    /// data descriptor flag has them zeroed in its local header and written
+    /// after its data instead, as streaming tools do.
+    fn test_zip(entries: &[TestEntry]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for entry in entries {
+            let mut crc = Crc::new();
+            crc.update(entry.data);
+            let data = match entry.method {
+                0 => entry.data.to_vec(),
+                8 => {
+                    let mut encoder = flate2::write::DeflateEncoder::new(
+                            Vec::new(), flate2::Compression::default());
+                    encoder.write_all(entry.data).unwrap();
+                    encoder.finish().unwrap()
+                }
+                method => panic!("test_zip can't compress with {}", method),
+            };
+            let (compressed_size, size) = (data.len() as u32, 
+                    entry.data.len() as u32);
+            let descriptor = entry.flags & FLAG_DATA_DESCRIPTOR != 0;
+            let offset = zip.len() as u32;
+            zip.extend(b"PK\x03\x04");
+            zip.extend(20u16.to_le_bytes());
+            zip.extend(entry.flags.to_le_bytes());
+            zip.extend(entry.method.to_le_bytes());
+            // Time and date.
+            zip.extend([0u8; 4]);
+            if descriptor {
+                zip.extend([0u8; 12]);
+            } else {
+                zip.extend(crc.sum().to_le_bytes());
+                zip.extend(compressed_size.to_le_bytes());
+                zip.extend(size.to_le_bytes());
+            }
+            zip.extend((entry.name.len() as u16).to_le_bytes());
+            zip.extend(0u16.to_le_bytes());
+            zip.extend(entry.name);
+            zip.extend(&data);
+            if descriptor {
+                zip.extend(b"PK\x07\x08");
+                zip.extend(crc.sum().to_le_bytes());
+                zip.extend(compressed_size.to_le_bytes());
+                zip.extend(size.to_le_bytes());
+            }
+
+            let version_made_by = if entry.unix_mode == 0 {
+                20
+            } else {
+                HOST_UNIX << 8 | 20
+            };
+            central.extend(b"PK\x01\x02");
+            central.extend(version_made_by.to_le_bytes());
+            central.extend(20u16.to_le_bytes());
+            central.extend(entry.flags.to_le_bytes());
+            central.extend(entry.method.to_le_bytes());
+            central.extend([0u8; 4]);
+            central.extend(crc.sum().to_le_bytes());
+            central.extend(compressed_size.to_le_bytes());
+            central.extend(size.to_le_bytes());
+            central.extend((entry.name.len() as u16).to_le_bytes());
+            // Extra field and comment lengths, disk and internal attributes.
+            central.extend([0u8; 8]);
+            central.extend((entry.unix_mode << 16).to_le_bytes());
+            central.extend(offset.to_le_bytes());
+            central.extend(entry.name);
+        }
+        let cd_offset = zip.len() as u32;
+        zip.extend(&central);
+        zip.extend(b"PK\x05\x06");
+        zip.extend([0u8; 4]);
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((entries.len() as u16).to_le_bytes());
+        zip.extend((central.len() as u32).to_le_bytes());
+        zip.extend(cd_offset.to_le_bytes());
+        zip.extend(0u16.to_le_bytes());
+        zip
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extracts_into_a_root_behind_a_junction() {
+        let dir = temp_dir("junction-root");
+        let real_root = dir.join("D");
+        let root = dir.join("Utils");
+        fs::create_dir_all(&real_root).unwrap();
+        junction::create(&real_root, &root).unwrap();
+        let mut reader = Cursor::new(test_zip(&[
+                entry("MyApp/bin/MyApp.exe", b"MZ"),
+                entry("MyApp/../../escape.txt", b"no")]));
+        let entries = read_central_directory(&mut reader).unwrap();
+
+        let path = extract_file(&entries[0], &mut reader, &root).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"MZ");
+        assert!(real_root.join(r"MyApp\bin\MyApp.exe").exists());
+        assert!(matches!(extract_file(&entries[1], &mut reader, &root), 
+                Err(ZipError::PathEscape(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn folder_symlink_falls_back_to_a_junction() {
+        let root = temp_dir("symlink-junction");
+        let mut reader = Cursor::new(test_zip(&[
+                entry("data/config.txt", b"x=1"),
+                TestEntry { 
+                    unix_mode: 0o120777, 
+                    ..entry("current", b"data") 
+                }]));
+        let entries = read_central_directory(&mut reader).unwrap();
+        assert!(entries[1].is_symlink());
+
+        extract_file(&entries[0], &mut reader, &root).unwrap();
+        // Which one depends on whether this account may create symlinks.
+        let kind = extract_symlink(&entries[1], &mut reader, &root).unwrap();
+        assert!(kind == LinkKind::Symlink || kind == LinkKind::Junction);
+        assert_eq!(fs::read(root.join(r"current\config.txt")).unwrap(), 
+                b"x=1");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn short_paths_are_joined_as_they_are() {
+        let dir = Path::new("apps").join("MyApp");
+        assert_eq!(join_entry_path(&dir, "bin/MyApp.exe").unwrap(), 
+                dir.join("bin/MyApp.exe"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extracts_an_entry_deeper_than_max_path() {
+        let root = temp_dir("long-path");
+        let name = format!("node_modules/{}/index.js", 
+                ["deeply-nested-package"; 15].join("/node_modules/"));
+        assert!(root.join(&name).as_os_str().len() > 260);
+        let mut reader = Cursor::new(test_zip(&[entry(&name, b"x")]));
+        let entries = read_central_directory(&mut reader).unwrap();
+
+        let path = extract_file(&entries[0], &mut reader, &root).unwrap();
+        assert!(path.to_string_lossy().starts_with(r"\\?\"), "{:?}", path);
+        assert_eq!(fs::read(&path).unwrap(), b"x");
+        fs::remove_dir_all(fs::canonicalize(&root).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn extracts_entries_sized_by_a_data_descriptor() {
+        let root = temp_dir("data-descriptor");
+        let text = b"streamed by a tool that couldn't seek back".repeat(20);
+        let mut reader = Cursor::new(test_zip(&[
+                TestEntry { 
+                    flags: FLAG_DATA_DESCRIPTOR, 
+                    method: 8, 
+                    ..entry("readme.txt", &text) 
+                },
+                TestEntry { 
+                    flags: FLAG_DATA_DESCRIPTOR, 
+                    ..entry("MyApp.exe", b"MZ") 
+                }]));
+        let entries = read_central_directory(&mut reader).unwrap();
+        assert!(entries.iter().all(ZipEntry::has_data_descriptor));
+
+        for entry in &entries {
+            verify_file(entry, &mut reader).unwrap();
+        }
+        let path = extract_file(&entries[0], &mut reader, &root).unwrap();
+        assert_eq!(fs::read(path).unwrap(), text);
+        let path = extract_file(&entries[1], &mut reader, &root).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"MZ");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn decodes_names_as_utf8_only_when_flagged() {
+        let utf8 = "Résumé/naïve.txt";
+        let mut reader = Cursor::new(test_zip(&[
+                TestEntry { flags: FLAG_UTF8, ..entry(utf8, b"") },
+                TestEntry { 
+                    name: b"M\x81nchen/\x84rger.txt", 
+                    ..entry("", b"") 
+                },
+                TestEntry { name: b"\xe9t\xe9.txt", ..entry("", b"") }]));
+        let names: Vec<String> = read_central_directory(&mut reader).unwrap()
+            .into_iter()
+            .map(|entry| entry.file_name)
+            .collect();
+        assert_eq!(names, [utf8, "München/ärger.txt", "ΘtΘ.txt"]);
+
+        let mut reader = Cursor::new(test_zip(&[TestEntry { 
+            name: b"\xe9t\xe9.txt", 
+            flags: FLAG_UTF8, 
+            ..entry("", b"") 
+        }]));
+        assert!(matches!(read_central_directory(&mut reader), 
+                Err(ZipError::Invalid(_))));
+    }
+
+    #[test]
+    fn backslashes_in_names_become_slashes() {
+        let mut reader = Cursor::new(test_zip(&[
+                entry(r"dir\sub\file.txt", b"x"),
+                entry(r"..\evil", b"no")]));
+        let entries = read_central_directory(&mut reader).unwrap();
+        assert_eq!(entries[0].file_name, "dir/sub/file.txt");
+        assert_eq!(entries[1].file_name, "../evil");
+
+        let dir = Path::new("apps").join("MyApp");
+        assert_eq!(join_entry_path(&dir, &entries[0].file_name).unwrap(), 
+                dir.join("dir/sub/file.txt"));
+        assert!(matches!(join_entry_path(&dir, &entries[1].file_name), 
+                Err(ZipError::PathEscape(_))));
+    }
+
+    #[test]
+    fn duplicate_entries_fail_unless_the_first_is_kept() {
+        let zip = test_zip(&[
+                entry("bin/MyApp.exe", b"first"),
+                entry("readme.txt", b"read me"),
+                entry("bin/MyApp.exe", b"second")]);
+        assert!(matches!(read_central_directory(&mut Cursor::new(&zip)), 
+                Err(ZipError::DuplicateEntry(name)) 
+                    if name == "bin/MyApp.exe"));
+
+        let mut reader = Cursor::new(&zip);
+        let (entries, duplicates) = 
+                read_central_directory_keep_first(&mut reader).unwrap();
+        let names: Vec<&str> = entries.iter()
+            .map(|entry| entry.file_name.as_str())
+            .collect();
+        assert_eq!(names, ["bin/MyApp.exe", "readme.txt"]);
+        assert_eq!(duplicates, ["bin/MyApp.exe"]);
+        assert_eq!(entries[0].local_header_offset, 0);
+        assert_eq!(entries[0].uncompressed_size, 5);
+    }
+
+    #[test]
+    fn adversarial_lengths_are_errors_not_panics() {
+        let zip = test_zip(&[entry("MyApp.exe", b"MZ")]);
+        let cd_start = zip.len() - 22 - (46 + "MyApp.exe".len());
+        let patched = |offset: usize, bytes: &[u8]| {
+            let mut zip = zip.clone();
+            zip[offset..offset + bytes.len()].copy_from_slice(bytes);
+            read_central_directory(&mut Cursor::new(zip))
+        };
+
+        // Name and extra field lengths running past the central directory.
+        assert!(matches!(patched(cd_start + 28, &u16::MAX.to_le_bytes()), 
+                Err(ZipError::Truncated(what)) if what == "file name"));
+        assert!(matches!(patched(cd_start + 30, &u16::MAX.to_le_bytes()), 
+                Err(ZipError::Truncated(what)) if what == "extra fields"));
+        // A central directory said to start near the end of the 4 GB range.
+        let cd_offset = zip.len() - 22 + 16;
+        assert!(matches!(patched(cd_offset, &0xFFFF_FFF0u32.to_le_bytes()), 
+                Err(ZipError::Truncated(what)) if what == "central directory"));
+
+        // A compressed size near u32::MAX that the local header doesn't 
+        // repeat.
+        let mut reader = Cursor::new(zip.clone());
+        let mut entries = read_central_directory(&mut reader).unwrap();
+        entries[0].compressed_size = 0xFFFF_FFFE;
+        assert!(matches!(verify_file(&entries[0], &mut reader), 
+                Err(ZipError::Invalid(_))));
+        // One whose data would end past u64::MAX, from a Zip64 size with a 
+        // data descriptor, so there's no local size to compare.
+        entries[0].compressed_size = u64::MAX - 8;
+        entries[0].flags |= FLAG_DATA_DESCRIPTOR;
+        assert!(matches!(verify_file(&entries[0], &mut reader), 
+                Err(ZipError::Invalid(_))));
+        let root = temp_dir("adversarial-lengths");
+        assert!(matches!(extract_file(&entries[0], &mut reader, &root), 
+                Err(ZipError::Invalid(_))));
+        assert!(!root.join("MyApp.exe").exists());
+        fs::remove_dir_all(&root).unwrap();
+        // A local header offset beyond the end of the file.
+        entries[0].local_header_offset = u64::MAX - 8;
+        assert!(matches!(verify_file(&entries[0], &mut reader), 
+                Err(ZipError::Truncated(_))));
+    }
+}